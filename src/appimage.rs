@@ -0,0 +1,387 @@
+use std::{
+    collections::HashSet,
+    fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    thread,
+};
+
+use crate::{cli, config::Config};
+
+/// Makes the AppImages in `dirs` look like installed apps. The desktop file
+/// and icon embedded in each one are extracted into
+/// `$XDG_CACHE_HOME/yappla/appimages`, with `Exec` pointing at the AppImage.
+/// Extraction runs again only when an AppImage is newer than its desktop
+/// file. Returns the directory with the generated desktop files.
+///
+/// Extracting runs the AppImages, so it happens in a background thread that
+/// doesn't hold up the window: the list shows what earlier runs extracted,
+/// and new or changed AppImages show up the next time.
+pub fn sync(dirs: &[PathBuf]) -> PathBuf {
+    let root = Config::cache_dir().join("appimages");
+    let applications = root.join("applications");
+    let dirs = dirs.to_vec();
+
+    thread::spawn(move || update(&dirs, &root));
+
+    applications
+}
+
+fn update(dirs: &[PathBuf], root: &Path) {
+    let applications = root.join("applications");
+    let icons = root.join("icons");
+    let mut stems = HashSet::new();
+
+    remove_abandoned_work_dirs(root);
+
+    for dir in dirs {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            let stem = match path.file_stem().and_then(|it| it.to_str()) {
+                Some(stem) if is_appimage(&path) => stem.to_string(),
+                _ => continue,
+            };
+
+            let desktop_file = applications.join(format!("appimage-{}.desktop", stem));
+            stems.insert(stem.clone());
+
+            if modified(&desktop_file) >= modified(&path) {
+                continue;
+            }
+
+            // Without an embedded entry, the AppImage is still listed under
+            // its file name.
+            let content = extract(&path, &stem, root, &icons).unwrap_or_else(|err| {
+                if cli::has_flag("--debug") {
+                    eprintln!("Failed to extract {}: {}", path.display(), err);
+                }
+
+                format!(
+                    "[Desktop Entry]\nType=Application\nName={}\nExec={} %U\n",
+                    stem,
+                    quote(&path.to_string_lossy())
+                )
+            });
+
+            let result = fs::create_dir_all(&applications)
+                .and_then(|_| write_atomically(&desktop_file, content.as_bytes()));
+
+            if let Err(err) = result {
+                eprintln!("Failed to write {}: {}", desktop_file.display(), err);
+            }
+        }
+    }
+
+    // Forget AppImages that were deleted. Files being written by another
+    // yappla have a suffix after the extension and are left alone.
+    for dir in [&applications, &icons] {
+        for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+
+            if let Some(stem) = generated_stem(&name)
+                && !stems.contains(stem)
+            {
+                let _ = fs::remove_file(entry.path());
+            }
+        }
+    }
+}
+
+/// Writes through a temporary file, so that a yappla listing the directory
+/// at the same time never reads half of it.
+fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let temporary = temporary_path(path);
+    fs::write(&temporary, content)?;
+    fs::rename(&temporary, path)
+}
+
+fn temporary_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", process::id()));
+    path.with_file_name(name)
+}
+
+/// Each yappla extracts in `extract-<pid>`. The ones of processes that exited
+/// in the middle, like when an app was launched before the extraction was
+/// done, are removed.
+fn remove_abandoned_work_dirs(root: &Path) {
+    for entry in fs::read_dir(root).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        let pid = match name.strip_prefix("extract-").and_then(|it| it.parse::<u32>().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        if pid != process::id() && !Path::new(&format!("/proc/{}", pid)).exists() {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+/// The AppImage a file generated by `update` belongs to. Desktop files are
+/// named `appimage-<stem>.desktop`, icons `<stem>.<ext>`.
+fn generated_stem(name: &str) -> Option<&str> {
+    match name.rsplit_once('.')? {
+        (stem, "desktop") => stem.strip_prefix("appimage-"),
+        (stem, "svg" | "png") => Some(stem),
+        _ => None,
+    }
+}
+
+fn is_appimage(path: &Path) -> bool {
+    let is_appimage = path
+        .extension()
+        .and_then(|it| it.to_str())
+        .is_some_and(|it| it.eq_ignore_ascii_case("appimage"));
+
+    is_appimage
+        && fs::metadata(path).is_ok_and(|it| it.is_file() && it.permissions().mode() & 0o111 != 0)
+}
+
+fn modified(path: &Path) -> Option<std::time::SystemTime> {
+    fs::metadata(path).and_then(|it| it.modified()).ok()
+}
+
+/// Returns the rewritten embedded desktop file, copying its icon to `icons`.
+fn extract(appimage: &Path, stem: &str, root: &Path, icons: &Path) -> io::Result<String> {
+    // Another yappla may be extracting at the same time.
+    let work = root.join(format!("extract-{}", process::id()));
+    let _ = fs::remove_dir_all(&work);
+    fs::create_dir_all(&work)?;
+
+    let result = extract_in(appimage, stem, &work, icons);
+    let _ = fs::remove_dir_all(&work);
+    result
+}
+
+fn extract_in(appimage: &Path, stem: &str, work: &Path, icons: &Path) -> io::Result<String> {
+    run_extract(appimage, work, "*.desktop")?;
+
+    let squashfs = work.join("squashfs-root");
+
+    // The entry describing the AppImage sits at the top, others may come with
+    // the bundled files.
+    let desktop = fs::read_dir(&squashfs)?
+        .flatten()
+        .map(|it| it.path())
+        .find(|it| it.extension().and_then(|it| it.to_str()) == Some("desktop"))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no desktop file inside"))?;
+
+    let content = fs::read_to_string(desktop)?;
+
+    let icon_name = content
+        .lines()
+        .find_map(|it| it.strip_prefix("Icon="))
+        .map(str::trim);
+
+    let icon = icon_name.and_then(|name| {
+        for (file, extension) in [
+            (format!("{}.svg", name), "svg"),
+            (format!("{}.png", name), "png"),
+            (".DirIcon".to_string(), "png"),
+        ] {
+            let _ = run_extract(appimage, work, &file);
+            let source = squashfs.join(&file);
+
+            if source.is_file() {
+                let target = icons.join(format!("{}.{}", stem, extension));
+                let temporary = temporary_path(&target);
+                let copied = fs::create_dir_all(icons)
+                    .and_then(|_| fs::copy(&source, &temporary))
+                    .and_then(|_| fs::rename(&temporary, &target));
+
+                return copied.ok().map(|_| target);
+            }
+        }
+
+        None
+    });
+
+    Ok(rewrite(&content, appimage, icon.as_deref()))
+}
+
+fn run_extract(appimage: &Path, work: &Path, pattern: &str) -> io::Result<()> {
+    let status = Command::new(appimage)
+        .args(["--appimage-extract", pattern])
+        .current_dir(work)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("--appimage-extract failed with {}", status)))
+    }
+}
+
+/// Points every `Exec` at the AppImage, keeping the arguments, and `Icon` at
+/// the extracted icon. `TryExec` names a program inside the AppImage, so it
+/// is dropped.
+fn rewrite(content: &str, appimage: &Path, icon: Option<&Path>) -> String {
+    let program = quote(&appimage.to_string_lossy());
+    let mut result = String::with_capacity(content.len());
+
+    for line in content.lines() {
+        if let Some(exec) = line.strip_prefix("Exec=") {
+            result.push_str(&format!("Exec={}{}", program, arguments(exec)));
+        } else if line.starts_with("TryExec=") {
+            continue;
+        } else if let Some(icon) = icon
+            && line.starts_with("Icon=")
+        {
+            result.push_str(&format!("Icon={}", icon.display()));
+        } else {
+            result.push_str(line);
+        }
+
+        result.push('\n');
+    }
+
+    result
+}
+
+/// Everything after the program of an `Exec` value, with the leading space.
+fn arguments(exec: &str) -> &str {
+    let exec = exec.trim_start();
+
+    let end = match exec.strip_prefix('"') {
+        Some(rest) => {
+            let mut escaped = false;
+            let mut end = exec.len();
+
+            for (i, char) in rest.char_indices() {
+                if escaped {
+                    escaped = false;
+                } else if char == '\\' {
+                    escaped = true;
+                } else if char == '"' {
+                    end = i + 2;
+                    break;
+                }
+            }
+
+            end
+        }
+        None => exec.find(char::is_whitespace).unwrap_or(exec.len()),
+    };
+
+    &exec[end..]
+}
+
+/// Quotes `value` as one `Exec` argument. Backslashes are doubled twice, as
+/// the value is unescaped as a string before it is split into arguments, and
+/// `%` is doubled so that it isn't taken for a field code.
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for char in value.chars() {
+        match char {
+            '"' | '`' | '$' => {
+                quoted.push_str("\\\\");
+                quoted.push(char);
+            }
+            '\\' => quoted.push_str("\\\\\\\\"),
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(char),
+        }
+    }
+
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{arguments, generated_stem, quote, rewrite};
+    use crate::exec::{self, ExecContext, Target};
+
+    /// The argv `exec` starts with `targets`.
+    fn expand(exec: &str, targets: &[Target]) -> Vec<Vec<String>> {
+        let context = ExecContext {
+            name: "App",
+            icon: None,
+            desktop_file: None,
+            targets,
+        };
+
+        exec::expand(exec, &context).unwrap()
+    }
+
+    #[test]
+    fn generated_stems() {
+        assert_eq!(generated_stem("appimage-Krita.desktop"), Some("Krita"));
+        assert_eq!(generated_stem("Krita.png"), Some("Krita"));
+        assert_eq!(generated_stem("appimage-tool.svg"), Some("appimage-tool"));
+        assert_eq!(generated_stem("appimage-tool.desktop.1234"), None);
+        assert_eq!(generated_stem("Krita.desktop"), None);
+    }
+
+    #[test]
+    fn quoted_paths_round_trip() {
+        let paths = [
+            "/home/user/Apps/Krita.AppImage",
+            "/home/user/My Apps/Krita.AppImage",
+            r#"/home/user/"quoted"/a\b/$HOME/`x`/100%/%f.AppImage"#,
+        ];
+
+        for path in paths {
+            assert_eq!(expand(&quote(path), &[]), [[path]]);
+        }
+
+        assert_eq!(quote(r#"/a "b"/%u"#), r#""/a \\"b\\"/%%u""#);
+        assert_eq!(quote(r"/a\b"), r#""/a\\\\b""#);
+    }
+
+    #[test]
+    fn program_arguments() {
+        assert_eq!(arguments("krita %F"), " %F");
+        assert_eq!(arguments("krita"), "");
+        assert_eq!(arguments("  AppRun --new-window %U"), " --new-window %U");
+        assert_eq!(arguments(r#""/opt/my app/run" %u"#), " %u");
+        assert_eq!(arguments(r#""/opt/a \"b\"/run" --x"#), " --x");
+    }
+
+    #[test]
+    fn rewritten_entry() {
+        let content = "[Desktop Entry]\nType=Application\nName=Krita\nTryExec=krita\n\
+                       Exec=krita %F\nIcon=krita\n";
+        let appimage = Path::new("/home/user/My Apps/krita 5%.AppImage");
+        let icon = Path::new("/home/user/.cache/yappla/appimages/icons/krita 5%.png");
+
+        let rewritten = rewrite(content, appimage, Some(icon));
+
+        assert_eq!(
+            rewritten,
+            "[Desktop Entry]\nType=Application\nName=Krita\n\
+             Exec=\"/home/user/My Apps/krita 5%%.AppImage\" %F\n\
+             Icon=/home/user/.cache/yappla/appimages/icons/krita 5%.png\n"
+        );
+
+        let exec = rewritten.lines().find_map(|it| it.strip_prefix("Exec=")).unwrap();
+        let targets = [Target::Path("/tmp/a.kra".into())];
+        assert_eq!(
+            expand(exec, &targets),
+            [["/home/user/My Apps/krita 5%.AppImage", "/tmp/a.kra"]]
+        );
+    }
+
+    #[test]
+    fn icon_kept_without_an_extracted_one() {
+        let content = "[Desktop Entry]\nExec=\"/opt/app/AppRun\"\nIcon=app\n";
+        let rewritten = rewrite(content, Path::new("/apps/App.AppImage"), None);
+
+        assert_eq!(rewritten, "[Desktop Entry]\nExec=\"/apps/App.AppImage\"\nIcon=app\n");
+    }
+}
@@ -0,0 +1,23 @@
+pub fn mode() -> String {
+    std::env::args().nth(1).unwrap_or_default()
+}
+
+pub fn has_flag(flag: &str) -> bool {
+    std::env::args().skip(2).any(|it| it == flag)
+}
+
+/// Arguments following `flag`, up to the next `--` option.
+pub fn values(flag: &str) -> Vec<String> {
+    std::env::args()
+        .skip(2)
+        .skip_while(|it| it != flag)
+        .skip(1)
+        .take_while(|it| !it.starts_with("--"))
+        .collect()
+}
+
+/// First argument after the mode that isn't an option, like the file of
+/// `yappla open-with <file>`.
+pub fn argument() -> Option<String> {
+    std::env::args().skip(2).find(|it| !it.starts_with("--"))
+}
@@ -0,0 +1,211 @@
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use serde::{Deserialize, Deserializer};
+
+/// User settings from `$XDG_CONFIG_HOME/yappla/config.json`. Every key is
+/// optional, a missing or broken file gives the defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Terminal used for `Terminal=true` apps and `history --exec`, e.g.
+    /// `["foot"]` or `["wezterm", "start", "--"]`. A single known emulator
+    /// name gets its own way of passing the command.
+    pub terminal: Option<Vec<String>>,
+    /// Icon size in pixels. When unset, the theme decides with
+    /// `-gtk-icon-size` on `image.icon`.
+    pub icon_size: Option<i32>,
+    /// File that stdout and stderr of launched apps are appended to. They go
+    /// to `/dev/null` when unset.
+    pub launch_log: Option<PathBuf>,
+    /// How apps from `apps` mode are started.
+    pub launch: LaunchStrategy,
+    /// Environment for apps with `PrefersNonDefaultGPU=true`.
+    pub gpu_offload: GpuOffload,
+    /// How queries are matched against items.
+    pub search: SearchAlgorithm,
+    /// Ranking of often and recently launched items.
+    pub frecency: FrecencyConfig,
+    /// Pinned and hidden apps and aliases of `apps` mode.
+    pub apps: AppsConfig,
+    /// Where apps are found besides the XDG data directories.
+    pub sources: SourcesConfig,
+}
+
+/// Apps are referred to by desktop-file ID, like `firefox.desktop`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct AppsConfig {
+    /// Listed first, in this order, when the query is empty.
+    pub pinned: Vec<String>,
+    /// Left out of the list, unless `--show-hidden` is passed.
+    pub hidden: Vec<String>,
+    /// Queries that put an app on top, like `"ff": "firefox.desktop"`. Kept
+    /// in lower case, like the query they are compared with.
+    #[serde(deserialize_with = "lowercase_keys")]
+    pub aliases: HashMap<String, String>,
+    /// List all apps when the query is empty, instead of the categories of
+    /// the XDG menu.
+    pub flat: bool,
+}
+
+fn lowercase_keys<'de, D>(deserializer: D) -> Result<HashMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let map = HashMap::<String, String>::deserialize(deserializer)?;
+    Ok(map.into_iter().map(|(key, value)| (key.to_lowercase(), value)).collect())
+}
+
+/// Paths may start with `~/`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SourcesConfig {
+    pub flatpak: bool,
+    pub snap: bool,
+    /// `~/.nix-profile` and the per-user profile of NixOS.
+    pub nix: bool,
+    /// Directories with `.AppImage` files. Empty by default, since listing
+    /// them means running them to extract their desktop files.
+    pub appimages: Vec<PathBuf>,
+    /// Further directories with `.desktop` files.
+    pub directories: Vec<PathBuf>,
+}
+
+impl Default for SourcesConfig {
+    fn default() -> Self {
+        Self {
+            flatpak: true,
+            snap: true,
+            nix: true,
+            appimages: Vec::new(),
+            directories: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct FrecencyConfig {
+    /// Days after which a launch counts half as much.
+    pub half_life_days: f64,
+    /// Launches kept per mode, older ones are forgotten.
+    pub max_launches: usize,
+}
+
+impl Default for FrecencyConfig {
+    fn default() -> Self {
+        Self {
+            half_life_days: 14.0,
+            max_launches: 500,
+        }
+    }
+}
+
+/// `"direct"`, `"systemd"` or `{ "wrapper": ["uwsm", "app", "--"] }`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LaunchStrategy {
+    /// Spawn the app as is.
+    #[default]
+    Direct,
+    /// Put the app in its own transient scope with `systemd-run --user`.
+    Systemd,
+    /// Prefix the command with a launcher like `uwsm app --` or `app2unit`.
+    Wrapper(Vec<String>),
+}
+
+/// `"prime"`, `"nvidia"`, `"off"` or `{ "env": { "VAR": "value" } }`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuOffload {
+    /// `DRI_PRIME=1`, which Mesa drivers understand.
+    #[default]
+    Prime,
+    /// The PRIME render offload variables of the NVIDIA driver.
+    Nvidia,
+    /// Ignore `PrefersNonDefaultGPU`.
+    Off,
+    /// Variables of your own.
+    Env(HashMap<String, String>),
+}
+
+/// `"fuzzy"` or `"strsim"`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchAlgorithm {
+    /// The chars of the query in order, scored like fzf.
+    #[default]
+    Fuzzy,
+    /// Jaro–Winkler for queries of up to three chars, normalized
+    /// Levenshtein for longer ones.
+    Strsim,
+}
+
+impl Config {
+    pub fn get() -> &'static Config {
+        static CONFIG: OnceLock<Config> = OnceLock::new();
+        CONFIG.get_or_init(Self::load)
+    }
+
+    /// Replaces a leading `~` of a configured path with the home directory.
+    pub fn expand_home(path: &Path) -> PathBuf {
+        match (path.strip_prefix("~"), env::home_dir()) {
+            (Ok(rest), Some(home)) => home.join(rest),
+            _ => path.to_path_buf(),
+        }
+    }
+
+    pub fn config_dir() -> PathBuf {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|it| it.join(".config")))
+            .unwrap_or_else(|| PathBuf::from("/"))
+            .join("yappla")
+    }
+
+    /// Changes the config file with `change`. Keys yappla doesn't know are
+    /// kept, but the formatting of the file is not. The config already loaded
+    /// stays as it was.
+    pub fn update(change: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>)) -> io::Result<()> {
+        let path = Self::config_dir().join("config.json");
+
+        let mut config = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => serde_json::Map::new(),
+            Err(err) => return Err(err),
+        };
+
+        change(&mut config);
+
+        fs::create_dir_all(Self::config_dir())?;
+        fs::write(&path, serde_json::to_string_pretty(&config)? + "\n")
+    }
+
+    /// `$XDG_CACHE_HOME/yappla`, for things that can be rebuilt any time.
+    pub fn cache_dir() -> PathBuf {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::home_dir().map(|it| it.join(".cache")))
+            .unwrap_or_else(|| PathBuf::from("/tmp"))
+            .join("yappla")
+    }
+
+    fn load() -> Config {
+        let path = Self::config_dir().join("config.json");
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => return Config::default(),
+        };
+
+        serde_json::from_str(&content).unwrap_or_else(|err| {
+            eprintln!("Failed to parse {}: {}", path.display(), err);
+            Config::default()
+        })
+    }
+}
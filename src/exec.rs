@@ -0,0 +1,649 @@
+use std::{
+    env,
+    ffi::{CStr, CString, OsStr},
+    fmt::Display,
+    fs,
+    os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+    path::{Path, PathBuf},
+    ptr,
+};
+
+/// URI schemes without `//` that are recognized in queries.
+const QUERY_SCHEMES: [&str; 7] = ["mailto", "magnet", "tel", "sms", "xmpp", "geo", "news"];
+
+/// Values the field codes of an `Exec` key expand to.
+pub struct ExecContext<'a> {
+    /// Translated `Name`, for `%c`.
+    pub name: &'a str,
+    /// `Icon`, for `%i`.
+    pub icon: Option<&'a str>,
+    /// Location of the desktop file, for `%k`.
+    pub desktop_file: Option<&'a Path>,
+    /// Files and URLs to open, for `%f`, `%F`, `%u` and `%U`.
+    pub targets: &'a [Target],
+}
+
+/// A file or URL passed to an app.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Target {
+    Path(PathBuf),
+    Uri(String),
+}
+
+impl Target {
+    /// Parses a command line or query argument. `file://` URLs become paths,
+    /// `~/` and `~user/` are expanded and relative paths are resolved against the current
+    /// directory, since the app may run somewhere else.
+    pub fn parse(value: &str) -> Target {
+        if let Some(path) = value.strip_prefix("file://") {
+            // Drop the host part of `file://host/path`.
+            let path = &path[path.find('/').unwrap_or(path.len())..];
+            return Target::Path(PathBuf::from(percent_decode(path)));
+        }
+
+        // A file named like `notes:1` in the current directory is still a
+        // file.
+        if Self::is_uri(value) && !Path::new(value).exists() {
+            return Target::Uri(value.to_string());
+        }
+
+        let path = Self::expand_tilde(value).unwrap_or_else(|| PathBuf::from(value));
+
+        match env::current_dir() {
+            Ok(dir) if path.is_relative() => Target::Path(dir.join(path)),
+            _ => Target::Path(path),
+        }
+    }
+
+    /// Whether a word of the query is meant as a file or URL rather than as
+    /// part of the app name, like `~/pic.png` in `gimp ~/pic.png`.
+    pub fn looks_like_target(value: &str) -> bool {
+        value.starts_with('/')
+            || Self::expand_tilde(value).is_some()
+            || value.starts_with("./")
+            || value.starts_with("../")
+            || Self::is_query_uri(value)
+    }
+
+    /// Expands `~`, `~/...`, `~user` and `~user/...`. `None` for other values
+    /// and for unknown users.
+    fn expand_tilde(value: &str) -> Option<PathBuf> {
+        let rest = value.strip_prefix('~')?;
+        let (user, rest) = rest.split_once('/').unwrap_or((rest, ""));
+
+        let home = if user.is_empty() {
+            env::home_dir()?
+        } else {
+            user_home(user)?
+        };
+
+        Some(if rest.is_empty() { home } else { home.join(rest) })
+    }
+
+    /// An RFC 3986 `scheme:...`, like `https://...`, `mailto:x@y` or
+    /// `magnet:?xt=...`.
+    fn is_uri(value: &str) -> bool {
+        match value.split_once(':') {
+            Some((scheme, rest)) => {
+                !rest.is_empty()
+                    && scheme.starts_with(|it: char| it.is_ascii_alphabetic())
+                    && scheme
+                        .chars()
+                        .all(|it| it.is_ascii_alphanumeric() || matches!(it, '+' | '-' | '.'))
+            }
+            None => false,
+        }
+    }
+
+    /// A URL typed into the query. Words like `todo:milk` are valid URIs too,
+    /// so only URLs with an authority and a few well-known schemes count.
+    fn is_query_uri(value: &str) -> bool {
+        let known_scheme = |(scheme, _): (&str, &str)| {
+            QUERY_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str())
+        };
+
+        Self::is_uri(value)
+            && (value.contains("://") || value.split_once(':').is_some_and(known_scheme))
+    }
+
+    /// Local path for `%f` and `%F`. Apps that only take files can't open
+    /// remote URLs, so those have none.
+    pub fn as_path(&self) -> Option<String> {
+        match self {
+            Target::Path(path) => Some(path.to_string_lossy().to_string()),
+            Target::Uri(_) => None,
+        }
+    }
+
+    /// URL for `%u` and `%U`, local files as `file://` URLs.
+    pub fn as_uri(&self) -> String {
+        match self {
+            Target::Path(path) => format!("file://{}", percent_encode(&path.to_string_lossy())),
+            Target::Uri(uri) => uri.clone(),
+        }
+    }
+}
+
+/// Home directory of `user` from the password database.
+fn user_home(user: &str) -> Option<PathBuf> {
+    let name = CString::new(user).ok()?;
+    // SAFETY: `passwd` is plain data that `getpwnam_r` fills in.
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buffer = vec![0 as libc::c_char; 16384];
+    let mut result = ptr::null_mut();
+
+    // SAFETY: every pointer is valid for the call, and the strings `passwd`
+    // points to live in `buffer`, which outlives their use below.
+    let status = unsafe {
+        libc::getpwnam_r(
+            name.as_ptr(),
+            &mut passwd,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+            &mut result,
+        )
+    };
+
+    if status != 0 || result.is_null() || passwd.pw_dir.is_null() {
+        return None;
+    }
+
+    // SAFETY: `pw_dir` is a null terminated string in `buffer`.
+    let dir = unsafe { CStr::from_ptr(passwd.pw_dir) };
+    Some(PathBuf::from(OsStr::from_bytes(dir.to_bytes())))
+}
+
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'/' | b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = path.get(i + 1..i + 3).and_then(|it| u8::from_str_radix(it, 16).ok());
+
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExecError {
+    UnterminatedQuote,
+    Empty,
+}
+
+impl Display for ExecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecError::UnterminatedQuote => write!(f, "Exec has an unterminated quote"),
+            ExecError::Empty => write!(f, "Exec has no program"),
+        }
+    }
+}
+
+/// Checks that `program` exists and is executable. Names without a slash are
+/// looked up in `$PATH`, the way `TryExec` and the shell resolve them.
+pub fn is_executable_in_path(program: &str) -> bool {
+    let is_executable = |path: &Path| {
+        fs::metadata(path)
+            .map(|it| it.is_file() && it.permissions().mode() & 0o111 != 0)
+            .unwrap_or(false)
+    };
+
+    if program.contains('/') {
+        return is_executable(Path::new(program));
+    }
+
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| is_executable(&dir.join(program))))
+        .unwrap_or(false)
+}
+
+/// Turns an `Exec` value into the argv to spawn, following the Desktop Entry
+/// spec: string escapes are resolved first, then quoting, then field codes.
+///
+/// Returns one argv per process to start. Apps that take a single file or
+/// URL (`%f`, `%u`) are started once for each target.
+pub fn expand(exec: &str, context: &ExecContext) -> Result<Vec<Vec<String>>, ExecError> {
+    let tokens = tokenize(&unescape_string(exec))?;
+
+    let takes_single = tokens.iter().any(|it| has_field_code(it, &['f', 'u']));
+    let takes_uri = tokens.iter().any(|it| has_field_code(it, &['u']));
+
+    // Remote URLs are skipped for apps that only open local files.
+    let mut runs: Vec<Option<&Target>> = context
+        .targets
+        .iter()
+        .filter(|it| takes_uri || it.as_path().is_some())
+        .map(Some)
+        .collect();
+
+    if !takes_single || runs.is_empty() {
+        runs = vec![None];
+    }
+
+    let mut argvs = Vec::with_capacity(runs.len());
+
+    for target in runs {
+        let mut argv = Vec::new();
+
+        for token in &tokens {
+            expand_token(token, context, target, &mut argv);
+        }
+
+        if argv.is_empty() {
+            return Err(ExecError::Empty);
+        }
+
+        argvs.push(argv);
+    }
+
+    Ok(argvs)
+}
+
+/// The file name of the program `exec` runs, looking through `env` and
+/// `flatpak run --command=`, so that apps can be found by their binary.
+pub fn program_name(exec: &str) -> Option<String> {
+    let tokens = tokenize(&unescape_string(exec)).ok()?;
+    let file_name = |token: &str| token.rsplit('/').next().unwrap_or(token).to_string();
+
+    let mut program = tokens
+        .iter()
+        .skip_while(|it| file_name(it) == "env" || it.contains('=') || it.starts_with('-'))
+        .map(|it| file_name(it))
+        .next()?;
+
+    if program == "flatpak"
+        && let Some(command) = tokens.iter().find_map(|it| it.strip_prefix("--command="))
+    {
+        program = file_name(command);
+    }
+
+    Some(program)
+}
+
+fn has_field_code(token: &str, codes: &[char]) -> bool {
+    let mut chars = token.chars();
+
+    while let Some(char) = chars.next() {
+        if char == '%' {
+            match chars.next() {
+                Some(code) if codes.contains(&code) => return true,
+                _ => {}
+            }
+        }
+    }
+
+    false
+}
+
+/// Resolves the escapes every desktop entry string value may contain.
+fn unescape_string(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            result.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => result.push(' '),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some('\\') => result.push('\\'),
+            // Not a string escape, leave it for the quoting rules.
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+
+    result
+}
+
+/// Splits a command line into arguments. Inside double quotes a backslash
+/// escapes `"`, `` ` ``, `$` and `\`. Single quotes aren't part of the spec,
+/// but are common enough in the wild that they are accepted like GLib does.
+fn tokenize(command: &str) -> Result<Vec<String>, ExecError> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut chars = command.chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            ' ' | '\t' | '\n' => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            '"' => {
+                in_token = true;
+
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped @ ('"' | '`' | '$' | '\\')) => current.push(escaped),
+                            Some(other) => {
+                                current.push('\\');
+                                current.push(other);
+                            }
+                            None => return Err(ExecError::UnterminatedQuote),
+                        },
+                        Some(char) => current.push(char),
+                        None => return Err(ExecError::UnterminatedQuote),
+                    }
+                }
+            }
+            '\'' => {
+                in_token = true;
+
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(char) => current.push(char),
+                        None => return Err(ExecError::UnterminatedQuote),
+                    }
+                }
+            }
+            '\\' => {
+                in_token = true;
+
+                if let Some(escaped) = chars.next() {
+                    current.push(escaped);
+                }
+            }
+            _ => {
+                in_token = true;
+                current.push(char);
+            }
+        }
+    }
+
+    if in_token {
+        tokens.push(current);
+    }
+
+    Ok(tokens)
+}
+
+/// Expands field codes in one argument. Standing alone, `%i` expands to two
+/// arguments and `%F`/`%U` to one argument per target; codes with nothing to
+/// expand to, like the deprecated ones, drop the argument entirely.
+fn expand_token(
+    token: &str,
+    context: &ExecContext,
+    target: Option<&Target>,
+    argv: &mut Vec<String>,
+) {
+    match token {
+        "%i" => {
+            if let Some(icon) = context.icon {
+                argv.push("--icon".to_string());
+                argv.push(icon.to_string());
+            }
+            return;
+        }
+        "%f" => {
+            argv.extend(target.and_then(Target::as_path));
+            return;
+        }
+        "%u" => {
+            argv.extend(target.map(Target::as_uri));
+            return;
+        }
+        "%F" => {
+            argv.extend(context.targets.iter().filter_map(Target::as_path));
+            return;
+        }
+        "%U" => {
+            argv.extend(context.targets.iter().map(Target::as_uri));
+            return;
+        }
+        "%d" | "%D" | "%n" | "%N" | "%v" | "%m" => return,
+        "%k" if context.desktop_file.is_none() => return,
+        _ => {}
+    }
+
+    let mut result = String::with_capacity(token.len());
+    let mut chars = token.chars();
+
+    while let Some(char) = chars.next() {
+        if char != '%' {
+            result.push(char);
+            continue;
+        }
+
+        match chars.next() {
+            Some('%') => result.push('%'),
+            Some('c') => result.push_str(context.name),
+            Some('k') => {
+                if let Some(path) = context.desktop_file {
+                    result.push_str(&path.to_string_lossy());
+                }
+            }
+            Some('i') => {
+                if let Some(icon) = context.icon {
+                    result.push_str(icon);
+                }
+            }
+            Some('f') => {
+                if let Some(path) = target.and_then(Target::as_path) {
+                    result.push_str(&path);
+                }
+            }
+            Some('u') => {
+                if let Some(target) = target {
+                    result.push_str(&target.as_uri());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    argv.push(result);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        path::{Path, PathBuf},
+    };
+
+    use super::{ExecContext, ExecError, Target, expand, program_name, tokenize, unescape_string};
+
+    fn context<'a>(targets: &'a [Target]) -> ExecContext<'a> {
+        ExecContext {
+            name: "Text Editor",
+            icon: Some("accessories-text-editor"),
+            desktop_file: Some(Path::new("/usr/share/applications/editor.desktop")),
+            targets,
+        }
+    }
+
+    fn run(exec: &str, context: &ExecContext) -> Vec<String> {
+        let mut argvs = expand(exec, context).unwrap();
+        assert_eq!(argvs.len(), 1);
+        argvs.remove(0)
+    }
+
+    #[test]
+    fn home_paths() {
+        let home = env::home_dir().unwrap();
+
+        assert_eq!(Target::parse("~"), Target::Path(home.clone()));
+        assert_eq!(Target::parse("~/pic.png"), Target::Path(home.join("pic.png")));
+    }
+
+    #[test]
+    fn user_home_paths() {
+        assert!(Target::looks_like_target("~root/notes.txt"));
+        assert_eq!(
+            Target::parse("~root/notes.txt"),
+            Target::Path(PathBuf::from("/root/notes.txt"))
+        );
+    }
+
+    #[test]
+    fn unknown_user_is_not_a_target() {
+        assert!(!Target::looks_like_target("~no-such-user-for-yappla/x"));
+    }
+
+    #[test]
+    fn uris() {
+        let uris = [
+            "https://example.org/a b",
+            "mailto:x@y",
+            "magnet:?xt=urn:btih:0",
+            "sftp://host/",
+        ];
+
+        for uri in uris {
+            assert!(Target::looks_like_target(uri), "{}", uri);
+            assert_eq!(Target::parse(uri), Target::Uri(uri.to_string()));
+        }
+
+        assert!(!Target::looks_like_target("firefox"));
+        assert!(!Target::looks_like_target("note:"));
+        assert!(!Target::looks_like_target("1password:x"));
+    }
+
+    #[test]
+    fn unknown_schemes_only_from_the_command_line() {
+        assert!(!Target::looks_like_target("todo:milk"));
+        assert!(Target::looks_like_target("Tel:+123"));
+        assert_eq!(
+            Target::parse("steam:rungameid/1"),
+            Target::Uri("steam:rungameid/1".to_string())
+        );
+    }
+
+    #[test]
+    fn file_uris_become_paths() {
+        assert_eq!(
+            Target::parse("file:///home/user/My%20Pictures/a.png"),
+            Target::Path(PathBuf::from("/home/user/My Pictures/a.png"))
+        );
+    }
+
+    #[test]
+    fn double_quotes() {
+        assert_eq!(
+            tokenize(r#"sh -c "echo \"a\" \` \$HOME \\ \n""#).unwrap(),
+            ["sh", "-c", r#"echo "a" ` $HOME \ \n"#]
+        );
+        assert_eq!(tokenize(r#"a"b c"d  'e f'"#).unwrap(), ["ab cd", "e f"]);
+        assert_eq!(tokenize(r"a\ b").unwrap(), ["a b"]);
+        assert_eq!(tokenize(r#"app "unterminated"#), Err(ExecError::UnterminatedQuote));
+        assert_eq!(tokenize("app 'unterminated"), Err(ExecError::UnterminatedQuote));
+    }
+
+    #[test]
+    fn string_escapes_come_first() {
+        assert_eq!(unescape_string(r"a\sb\tc\\d\$"), "a b\tc\\d\\$");
+
+        // A literal backslash inside quotes is written `\\\\` in the file:
+        // `\\` for the string layer, then `\\` again for the quoting rules.
+        assert_eq!(run(r#"app "C:\\\\dir""#, &context(&[])), ["app", r"C:\dir"]);
+        assert_eq!(run(r#"app "\\$HOME""#, &context(&[])), ["app", "$HOME"]);
+    }
+
+    #[test]
+    fn field_codes() {
+        let context = context(&[]);
+
+        assert_eq!(run("app 100%%", &context), ["app", "100%"]);
+        assert_eq!(
+            run("app %i --title=%c %k", &context),
+            [
+                "app",
+                "--icon",
+                "accessories-text-editor",
+                "--title=Text Editor",
+                "/usr/share/applications/editor.desktop",
+            ]
+        );
+        assert_eq!(run("app %d %D %n %N %v %m %f %F %u %U", &context), ["app"]);
+    }
+
+    #[test]
+    fn missing_icon_and_desktop_file() {
+        let context = ExecContext { icon: None, desktop_file: None, ..context(&[]) };
+
+        assert_eq!(run("app %i %k --icon=%i", &context), ["app", "--icon="]);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(expand("", &context(&[])), Err(ExecError::Empty));
+        assert_eq!(expand("%f %U", &context(&[])), Err(ExecError::Empty));
+    }
+
+    #[test]
+    fn one_run_per_target() {
+        let targets = [
+            Target::Path(PathBuf::from("/tmp/a b.txt")),
+            Target::Uri("https://example.org/".to_string()),
+        ];
+        let context = context(&targets);
+
+        assert_eq!(expand("app %f", &context).unwrap(), [["app", "/tmp/a b.txt"]]);
+        assert_eq!(
+            expand("app --open=%u", &context).unwrap(),
+            [["app", "--open=file:///tmp/a%20b.txt"], ["app", "--open=https://example.org/"]]
+        );
+        assert_eq!(expand("app %F", &context).unwrap(), [vec!["app", "/tmp/a b.txt"]]);
+        assert_eq!(
+            expand("app %U", &context).unwrap(),
+            [vec!["app", "file:///tmp/a%20b.txt", "https://example.org/"]]
+        );
+    }
+
+    #[test]
+    fn program_names() {
+        let name = |exec| program_name(exec).unwrap();
+
+        assert_eq!(name("env FOO=1 /usr/bin/code %F"), "code");
+        assert_eq!(name("/usr/bin/env -i FOO=1 code --new-window"), "code");
+        assert_eq!(
+            name("flatpak run --command=gnome-text-editor org.gnome.TextEditor"),
+            "gnome-text-editor"
+        );
+        assert_eq!(name("/usr/bin/flatpak run org.gnome.TextEditor"), "flatpak");
+        assert_eq!(name(r#""/opt/My App/bin/my-app" %U"#), "my-app");
+        assert_eq!(program_name(""), None);
+        assert_eq!(program_name("\"unterminated"), None);
+    }
+}
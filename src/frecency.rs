@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    env, fs, io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{Config, FrecencyConfig};
+
+/// How much a frequently and recently used item is pushed up in search
+/// results. An item launched a lot gets at most this fraction added to its
+/// score, so a good match on the name still beats a habit.
+const MAX_BOOST: f64 = 0.5;
+
+/// Launch history of one mode, kept in `$XDG_STATE_HOME/yappla/<mode>.json`.
+/// Every launch counts for less the older it gets, halving every
+/// `frecency.half_life_days`.
+pub struct Frecency {
+    path: PathBuf,
+    launches: Vec<Launch>,
+    scores: HashMap<String, f64>,
+    max_launches: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Launch {
+    id: String,
+    /// Seconds since the Unix epoch.
+    time: u64,
+}
+
+impl Frecency {
+    pub fn load(mode: &str) -> Self {
+        Self::load_from(Self::path(mode), now(), &Config::get().frecency)
+    }
+
+    fn path(mode: &str) -> PathBuf {
+        state_dir().join(format!("{}.json", mode))
+    }
+
+    /// Reads the history at `path`, with scores as of `now`.
+    fn load_from(path: PathBuf, now: u64, config: &FrecencyConfig) -> Self {
+        let launches: Vec<Launch> = fs::read(&path)
+            .ok()
+            .and_then(|it| serde_json::from_slice(&it).ok())
+            .unwrap_or_default();
+
+        let half_life = (config.half_life_days * 24.0 * 60.0 * 60.0).max(1.0);
+        let mut scores = HashMap::new();
+
+        for launch in &launches {
+            let age = now.saturating_sub(launch.time) as f64;
+            *scores.entry(launch.id.clone()).or_insert(0.0) += 0.5f64.powf(age / half_life);
+        }
+
+        Self {
+            path,
+            launches,
+            scores,
+            max_launches: config.max_launches,
+        }
+    }
+
+    /// Sum of all launches of `id`, each weighted by its age.
+    pub fn score(&self, id: &str) -> f64 {
+        self.scores.get(id).copied().unwrap_or(0.0)
+    }
+
+    /// Factor a search score of `id` is multiplied with, between 1 and
+    /// `1 + MAX_BOOST`.
+    pub fn boost(&self, id: &str) -> f64 {
+        let score = self.score(id);
+        1.0 + MAX_BOOST * score / (score + 1.0)
+    }
+
+    /// Sorts `indices` by descending score, keeping the order of items that
+    /// were never launched.
+    pub fn sort<'a>(&self, indices: &mut [u32], id: impl Fn(u32) -> &'a str) {
+        indices.sort_by(|a, b| self.score(id(*b)).total_cmp(&self.score(id(*a))));
+    }
+
+    /// Appends a launch of `id` to the history file, dropping the oldest
+    /// launches beyond `frecency.max_launches`.
+    pub fn record(&self, id: &str) {
+        self.record_at(id, now());
+    }
+
+    fn record_at(&self, id: &str, time: u64) {
+        let launch = Launch {
+            id: id.to_string(),
+            time,
+        };
+
+        let skip = (self.launches.len() + 1).saturating_sub(self.max_launches);
+        let launches: Vec<&Launch> = self.launches.iter().chain([&launch]).skip(skip).collect();
+
+        if let Err(err) = self.save(&launches) {
+            eprintln!("Failed to write {}: {}", self.path.display(), err);
+        }
+    }
+
+    fn save(&self, launches: &[&Launch]) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let temporary = self.path.with_extension(format!("json.{}", std::process::id()));
+        fs::write(&temporary, serde_json::to_vec(launches)?)?;
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+fn state_dir() -> PathBuf {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|it| it.join(".local/state")))
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("yappla")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::Frecency;
+    use crate::config::FrecencyConfig;
+
+    const DAY: u64 = 24 * 60 * 60;
+    const NOW: u64 = 1_700_000_000;
+
+    struct TestFile(PathBuf);
+
+    impl TestFile {
+        /// A history file holding `launches` as `(id, seconds before NOW)`.
+        fn new(name: &str, launches: &[(&str, u64)]) -> Self {
+            let path = env::temp_dir().join(format!("yappla-{}-{}.json", name, std::process::id()));
+            let launches: Vec<_> = launches
+                .iter()
+                .map(|(id, age)| serde_json::json!({ "id": id, "time": NOW - age }))
+                .collect();
+
+            fs::write(&path, serde_json::to_vec(&launches).unwrap()).unwrap();
+            Self(path)
+        }
+
+        fn load(&self, max_launches: usize) -> Frecency {
+            let config = FrecencyConfig {
+                half_life_days: 1.0,
+                max_launches,
+            };
+
+            Frecency::load_from(self.0.clone(), NOW, &config)
+        }
+    }
+
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn half_life() {
+        let launches = [("a", 0), ("a", 0), ("b", DAY), ("c", 3 * DAY)];
+        let file = TestFile::new("frecency-decay", &launches);
+        let frecency = file.load(10);
+
+        assert_eq!(frecency.score("a"), 2.0);
+        assert_eq!(frecency.score("b"), 0.5);
+        assert_eq!(frecency.score("c"), 0.125);
+        assert_eq!(frecency.score("d"), 0.0);
+    }
+
+    #[test]
+    fn boost() {
+        let file = TestFile::new("frecency-boost", &[("a", 0), ("b", 0), ("b", 0), ("b", 0)]);
+        let frecency = file.load(10);
+
+        assert_eq!(frecency.boost("a"), 1.25);
+        assert_eq!(frecency.boost("b"), 1.375);
+        assert_eq!(frecency.boost("never"), 1.0);
+    }
+
+    #[test]
+    fn sort() {
+        let file = TestFile::new("frecency-sort", &[("b", 0), ("c", DAY), ("c", DAY), ("c", DAY)]);
+        let frecency = file.load(10);
+        let ids = ["a", "b", "c", "d"];
+        let mut indices = [0, 1, 2, 3];
+
+        frecency.sort(&mut indices, |it| ids[it as usize]);
+        assert_eq!(indices, [2, 1, 0, 3]);
+    }
+
+    #[test]
+    fn oldest_launches_are_dropped() {
+        let file = TestFile::new("frecency-trim", &[("a", 3 * DAY), ("b", 2 * DAY), ("c", DAY)]);
+
+        file.load(3).record_at("d", NOW);
+
+        let frecency = file.load(3);
+        let ids: Vec<&str> = frecency.launches.iter().map(|it| it.id.as_str()).collect();
+        assert_eq!(ids, ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn one_file_per_mode() {
+        assert!(Frecency::path("apps").ends_with("yappla/apps.json"));
+        assert!(Frecency::path("games").ends_with("yappla/games.json"));
+    }
+}
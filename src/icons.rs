@@ -0,0 +1,70 @@
+use std::{cell::RefCell, collections::HashMap, path::Path};
+
+use glib::object::Cast;
+use relm4::gtk::{self, gdk, gio};
+
+/// Shown for items that name an icon which can't be found anywhere.
+const FALLBACK_ICON: &str = "application-x-executable";
+
+const PIXMAPS_DIR: &str = "/usr/share/pixmaps";
+
+/// Resolves `Icon` values of desktop entries. Lookups happen when a row is
+/// bound, so only visible rows pay for them, and each name is resolved once.
+/// The image data itself is loaded by GTK when the row is drawn.
+#[derive(Default)]
+pub struct IconCache {
+    icons: RefCell<HashMap<String, gio::Icon>>,
+}
+
+impl IconCache {
+    pub fn get(&self, name: &str) -> gio::Icon {
+        if let Some(icon) = self.icons.borrow().get(name) {
+            return icon.clone();
+        }
+
+        let icon = Self::resolve(name)
+            .unwrap_or_else(|| gio::ThemedIcon::new(FALLBACK_ICON).upcast());
+
+        self.icons
+            .borrow_mut()
+            .insert(name.to_string(), icon.clone());
+
+        icon
+    }
+
+    /// Looks `name` up as an absolute path, in the icon theme and then in
+    /// `/usr/share/pixmaps`, where older apps still install their icons.
+    fn resolve(name: &str) -> Option<gio::Icon> {
+        let path = Path::new(name);
+
+        if path.is_absolute() {
+            return path.is_file().then(|| Self::file_icon(path));
+        }
+
+        if let Some(display) = gdk::Display::default() {
+            let theme = gtk::IconTheme::for_display(&display);
+
+            // Some entries wrongly use `Icon=name.png`, the theme wants `name`.
+            let theme_name = match path.extension().and_then(|it| it.to_str()) {
+                Some("png" | "svg" | "xpm") => path.file_stem().and_then(|it| it.to_str()),
+                _ => Some(name),
+            };
+
+            if let Some(theme_name) = theme_name
+                && theme.has_icon(theme_name)
+            {
+                return Some(gio::ThemedIcon::new(theme_name).upcast());
+            }
+        }
+
+        ["", ".png", ".svg", ".xpm"]
+            .iter()
+            .map(|extension| Path::new(PIXMAPS_DIR).join(format!("{}{}", name, extension)))
+            .find(|it| it.is_file())
+            .map(|it| Self::file_icon(&it))
+    }
+
+    fn file_icon(path: &Path) -> gio::Icon {
+        gio::FileIcon::new(&gio::File::for_path(path)).upcast()
+    }
+}
@@ -0,0 +1,711 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io,
+    os::unix::process::CommandExt,
+    path::Path,
+    process::{Command, Stdio},
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use glib::{object::Cast, variant::ToVariant};
+use relm4::gtk::{
+    gdk::{self, prelude::DisplayExt},
+    gio::{self, prelude::AppLaunchContextExt},
+};
+
+use crate::config::{Config, GpuOffload, LaunchStrategy};
+
+/// Variables yappla consumes itself and must not hand down to the apps it
+/// starts, their tokens are only valid once.
+const CONSUMED_VARIABLES: [&str; 2] = ["DESKTOP_STARTUP_ID", "XDG_ACTIVATION_TOKEN"];
+
+/// How long to wait for a D-Bus activated app to answer before falling back
+/// to `Exec`, so that a hung app can't freeze yappla.
+const DBUS_TIMEOUT_MS: i32 = 3000;
+
+static INITIAL_ENVIRONMENT: OnceLock<HashMap<OsString, OsString>> = OnceLock::new();
+
+/// Remembers the environment yappla was started with. Must be called before
+/// GTK is initialized, so that whatever GTK and GDK set for themselves can be
+/// left out of the environment of launched apps.
+pub fn capture_environment() {
+    INITIAL_ENVIRONMENT.get_or_init(|| env::vars_os().collect());
+}
+
+/// Hands out tokens that let the compositor focus the window of a launched
+/// app. A trait, so that launching can be exercised without a display.
+pub trait ActivationTokenProvider {
+    fn token(&self, desktop_file: Option<&Path>) -> Option<String>;
+}
+
+/// Gets tokens from GDK's app launch context, which requests an
+/// `xdg_activation_v1` token on Wayland and a startup notification ID on X11.
+pub struct DisplayTokenProvider;
+
+impl ActivationTokenProvider for DisplayTokenProvider {
+    fn token(&self, desktop_file: Option<&Path>) -> Option<String> {
+        let context = gdk::Display::default()?.app_launch_context();
+
+        let info = desktop_file
+            .and_then(gio::DesktopAppInfo::from_filename)
+            .map(|it| it.upcast::<gio::AppInfo>());
+
+        context
+            .startup_notify_id(info.as_ref(), &[])
+            .map(|it| it.to_string())
+    }
+}
+
+/// Environment that passes an activation token to a launched app. Wayland
+/// apps read `XDG_ACTIVATION_TOKEN`, X11 ones `DESKTOP_STARTUP_ID`.
+pub fn activation_env(
+    provider: &dyn ActivationTokenProvider,
+    desktop_file: Option<&Path>,
+) -> Vec<(&'static str, String)> {
+    match provider.token(desktop_file) {
+        Some(token) => vec![
+            ("XDG_ACTIVATION_TOKEN", token.clone()),
+            ("DESKTOP_STARTUP_ID", token),
+        ],
+        None => Vec::new(),
+    }
+}
+
+/// Starts a `DBusActivatable=true` app by calling
+/// `org.freedesktop.Application.Activate` on its well-known name, which is
+/// the desktop-file ID without `.desktop`, or `Open` when there are `uris`
+/// to pass. Activating an app that already runs just presents it, instead of
+/// starting a second instance.
+pub fn activate_dbus(
+    app_id: &str,
+    uris: &[String],
+    provider: &dyn ActivationTokenProvider,
+    desktop_file: Option<&Path>,
+) -> Result<(), glib::Error> {
+    let connection = gio::bus_get_sync(gio::BusType::Session, None::<&gio::Cancellable>)?;
+
+    call_activation(&connection, app_id, uris, provider.token(desktop_file))
+}
+
+fn call_activation(
+    connection: &gio::DBusConnection,
+    app_id: &str,
+    uris: &[String],
+    token: Option<String>,
+) -> Result<(), glib::Error> {
+    let (method, parameters) = activation_call(uris, token);
+
+    connection
+        .call_sync(
+            Some(app_id),
+            &object_path(app_id),
+            "org.freedesktop.Application",
+            method,
+            Some(&parameters),
+            None,
+            gio::DBusCallFlags::NONE,
+            DBUS_TIMEOUT_MS,
+            None::<&gio::Cancellable>,
+        )
+        .map(|_| ())
+}
+
+/// The object path an app exports `org.freedesktop.Application` on, its ID
+/// with `.` turned into `/` and `-` into `_`.
+fn object_path(app_id: &str) -> String {
+    format!("/{}", app_id.replace('.', "/").replace('-', "_"))
+}
+
+/// The method to call, `Activate`, or `Open` when there are `uris`, with its
+/// parameters. The token goes into the platform data under both the Wayland
+/// and the X11 key.
+fn activation_call(uris: &[String], token: Option<String>) -> (&'static str, glib::Variant) {
+    let platform_data = glib::VariantDict::new(None);
+
+    if let Some(token) = token {
+        platform_data.insert("activation-token", &token);
+        platform_data.insert("desktop-startup-id", &token);
+    }
+
+    if uris.is_empty() {
+        ("Activate", glib::Variant::tuple_from_iter([platform_data.end()]))
+    } else {
+        let uris = glib::Variant::array_from_iter::<String>(uris.iter().map(|it| it.to_variant()));
+        ("Open", glib::Variant::tuple_from_iter([uris, platform_data.end()]))
+    }
+}
+
+/// Returns the argv that starts `argv` with `strategy`. `app_id` is the
+/// desktop-file ID without `.desktop`, used to name the systemd scope.
+pub fn apply_strategy(strategy: &LaunchStrategy, app_id: &str, argv: Vec<String>) -> Vec<String> {
+    let prefix = match strategy {
+        LaunchStrategy::Direct => return argv,
+        LaunchStrategy::Systemd => vec![
+            "systemd-run".to_string(),
+            "--user".to_string(),
+            "--scope".to_string(),
+            format!("--unit={}", scope_name(app_id, &random_suffix())),
+            "--".to_string(),
+        ],
+        LaunchStrategy::Wrapper(wrapper) => wrapper.clone(),
+    };
+
+    prefix.into_iter().chain(argv).collect()
+}
+
+/// Number of GPUs that can render, counted by their `/dev/dri/renderD*`
+/// nodes.
+pub fn render_nodes() -> usize {
+    fs::read_dir("/dev/dri")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|it| it.file_name().to_string_lossy().starts_with("renderD"))
+        .count()
+}
+
+/// Environment that makes an app render on the GPU other than the default
+/// one, for `PrefersNonDefaultGPU=true`. Empty with fewer than two
+/// `render_nodes`, as there is no other GPU to prefer.
+pub fn gpu_offload_env(offload: &GpuOffload, render_nodes: usize) -> Vec<(String, String)> {
+    if render_nodes < 2 {
+        return Vec::new();
+    }
+
+    let pairs = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    };
+
+    match offload {
+        GpuOffload::Prime => pairs(&[("DRI_PRIME", "1")]),
+        GpuOffload::Nvidia => pairs(&[
+            ("__NV_PRIME_RENDER_OFFLOAD", "1"),
+            ("__VK_LAYER_NV_optimus", "NVIDIA_only"),
+            ("__GLX_VENDOR_LIBRARY_NAME", "nvidia"),
+        ]),
+        GpuOffload::Off => Vec::new(),
+        GpuOffload::Env(env) => {
+            let mut env: Vec<(String, String)> = env.clone().into_iter().collect();
+            env.sort();
+            env
+        }
+    }
+}
+
+/// Names the scope `app-yappla-<id>-<random>.scope`, following the
+/// `app[-<launcher>]-<ApplicationID>-<RANDOM>.scope` convention of
+/// systemd's desktop environment integration.
+fn scope_name(app_id: &str, random: &str) -> String {
+    format!("app-yappla-{}-{}.scope", escape_unit_name(app_id), random)
+}
+
+/// Escapes a string for a unit name like `systemd-escape` does. The dash is
+/// escaped too, since it separates the parts of the name.
+fn escape_unit_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+
+    for (i, byte) in name.bytes().enumerate() {
+        let allowed = byte.is_ascii_alphanumeric() || byte == b'_' || byte == b':';
+
+        if allowed || (byte == b'.' && i > 0) {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("\\x{:02x}", byte));
+        }
+    }
+
+    escaped
+}
+
+fn random_suffix() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|it| it.subsec_nanos())
+        .unwrap_or(0);
+
+    format!("{:08x}", nanos ^ std::process::id().rotate_left(16))
+}
+
+/// Spawns `command` fully detached from yappla: in its own session, with
+/// stdio going to `/dev/null` or the configured log file and with yappla's
+/// initial environment. The child is double forked, so it gets reparented to
+/// init right away and never lingers as a zombie of a long running yappla.
+///
+/// Variables explicitly set on `command` are kept as they are.
+pub fn spawn_detached(command: &mut Command) -> io::Result<()> {
+    restore_environment(command);
+
+    command.stdin(Stdio::null());
+
+    match &Config::get().launch_log {
+        Some(path) => {
+            let log = OpenOptions::new().create(true).append(true).open(path)?;
+            command.stdout(log.try_clone()?);
+            command.stderr(log);
+        }
+        None => {
+            command.stdout(Stdio::null());
+            command.stderr(Stdio::null());
+        }
+    }
+
+    // SAFETY: only async-signal-safe functions are called between `fork` and
+    // `exec`. Errors of the final `exec` still reach the parent, since the
+    // grandchild inherits the pipe `Command` uses to report them.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+
+            match libc::fork() {
+                -1 => Err(io::Error::last_os_error()),
+                0 => Ok(()),
+                _ => libc::_exit(0),
+            }
+        });
+    }
+
+    // Reaps the intermediate process, which exits as soon as it has forked.
+    command.spawn()?.wait().map(|_| ())
+}
+
+fn restore_environment(command: &mut Command) {
+    let initial = match INITIAL_ENVIRONMENT.get() {
+        Some(initial) => initial,
+        None => return,
+    };
+
+    let explicit: HashSet<OsString> = command
+        .get_envs()
+        .map(|(key, _)| key.to_os_string())
+        .collect();
+
+    for (key, value) in env::vars_os() {
+        if explicit.contains(&key) {
+            continue;
+        }
+
+        match initial.get(&key) {
+            Some(initial_value) if *initial_value != value => {
+                command.env(&key, initial_value);
+            }
+            Some(_) => {}
+            None => {
+                command.env_remove(&key);
+            }
+        }
+    }
+
+    for (key, value) in initial {
+        if !explicit.contains(key) && env::var_os(key).is_none() {
+            command.env(key, value);
+        }
+    }
+
+    for key in CONSUMED_VARIABLES {
+        if !explicit.contains(&OsString::from(key)) {
+            command.env_remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        io::{self, BufRead, BufReader},
+        path::Path,
+        process::{Child, Command, Stdio},
+        sync::mpsc,
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use glib::variant::ToVariant;
+    use relm4::gtk::gio;
+
+    use super::{
+        ActivationTokenProvider, activation_call, activation_env, apply_strategy, call_activation,
+        capture_environment, escape_unit_name, gpu_offload_env, object_path, restore_environment,
+        scope_name, spawn_detached,
+    };
+    use crate::config::{GpuOffload, LaunchStrategy};
+
+    struct FakeTokenProvider(Option<&'static str>);
+
+    impl ActivationTokenProvider for FakeTokenProvider {
+        fn token(&self, _: Option<&Path>) -> Option<String> {
+            self.0.map(str::to_string)
+        }
+    }
+
+    /// The activation variables as the launched child sees them.
+    fn child_tokens(provider: &dyn ActivationTokenProvider) -> String {
+        capture_environment();
+
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "echo \"$XDG_ACTIVATION_TOKEN;$DESKTOP_STARTUP_ID\""])
+            .envs(activation_env(provider, None));
+        restore_environment(&mut command);
+
+        let output = command.output().unwrap();
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    #[test]
+    fn activation_token_reaches_child() {
+        assert_eq!(child_tokens(&FakeTokenProvider(Some("token-1"))), "token-1;token-1");
+    }
+
+    #[test]
+    fn no_activation_token() {
+        assert!(activation_env(&FakeTokenProvider(None), None).is_empty());
+        assert_eq!(child_tokens(&FakeTokenProvider(None)), ";");
+    }
+
+    /// Set for `detached_child_outlives_yappla`, which runs this test binary
+    /// again as a stand-in for yappla.
+    const PID_FILE_VARIABLE: &str = "YAPPLA_TEST_PID_FILE";
+
+    /// `(state, parent pid)` from `/proc/<pid>/stat`.
+    fn process_state(pid: u32) -> Option<(char, u32)> {
+        let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+        // The command name is in parentheses and may contain spaces.
+        let mut fields = stat[stat.rfind(')')? + 1..].split_whitespace();
+        let state = fields.next()?.chars().next()?;
+        let parent = fields.next()?.parse().ok()?;
+
+        Some((state, parent))
+    }
+
+    fn wait_for_pid(path: &Path) -> u32 {
+        let start = Instant::now();
+
+        loop {
+            if let Some(pid) = fs::read_to_string(path)
+                .ok()
+                .and_then(|it| it.trim().parse().ok())
+            {
+                return pid;
+            }
+
+            assert!(start.elapsed() < Duration::from_secs(10), "no pid was written");
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// Launches a child and exits, when run by `detached_child_outlives_yappla`.
+    #[test]
+    fn spawn_detached_and_exit() {
+        let pid_file = match env::var_os(PID_FILE_VARIABLE) {
+            Some(pid_file) => pid_file,
+            None => return,
+        };
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo $$ > \"$0\"; exec sleep 30"]).arg(pid_file);
+
+        spawn_detached(&mut command).unwrap();
+    }
+
+    #[test]
+    fn detached_child_outlives_yappla() {
+        let pid_file = env::temp_dir().join(format!("yappla-test-{}.pid", std::process::id()));
+        let _ = fs::remove_file(&pid_file);
+
+        let mut yappla = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "launch::tests::spawn_detached_and_exit", "--quiet"])
+            .env(PID_FILE_VARIABLE, &pid_file)
+            .spawn()
+            .unwrap();
+        let yappla_pid = yappla.id();
+
+        assert!(yappla.wait().unwrap().success());
+
+        let pid = wait_for_pid(&pid_file);
+        let _ = fs::remove_file(&pid_file);
+
+        let state = process_state(pid);
+
+        // SAFETY: only sends a signal to the sleep started above.
+        unsafe {
+            libc::kill(pid as i32, libc::SIGKILL);
+        }
+
+        let (state, parent) = state.expect("the launched process is gone");
+        assert_ne!(state, 'Z', "the launched process is a zombie");
+        assert_ne!(parent, yappla_pid);
+        assert_ne!(parent, std::process::id());
+    }
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|it| it.to_string()).collect()
+    }
+
+    #[test]
+    fn direct_strategy_keeps_argv() {
+        let command = argv(&["firefox", "--new-window"]);
+        assert_eq!(apply_strategy(&LaunchStrategy::Direct, "firefox", command.clone()), command);
+    }
+
+    #[test]
+    fn systemd_strategy() {
+        let command = argv(&["nautilus", "--new-window"]);
+        let result = apply_strategy(&LaunchStrategy::Systemd, "org.gnome.Nautilus", command);
+
+        assert_eq!(result[..3], argv(&["systemd-run", "--user", "--scope"]));
+        assert!(result[3].starts_with("--unit=app-yappla-org.gnome.Nautilus-"));
+        assert!(result[3].ends_with(".scope"));
+        assert_eq!(result[4..], argv(&["--", "nautilus", "--new-window"]));
+    }
+
+    #[test]
+    fn wrapper_strategy() {
+        let wrapper = LaunchStrategy::Wrapper(argv(&["uwsm", "app", "--"]));
+
+        assert_eq!(
+            apply_strategy(&wrapper, "foot", argv(&["foot"])),
+            argv(&["uwsm", "app", "--", "foot"])
+        );
+    }
+
+    #[test]
+    fn scope_names() {
+        assert_eq!(scope_name("firefox", "0a1b2c3d"), "app-yappla-firefox-0a1b2c3d.scope");
+        assert_eq!(
+            scope_name("org.kde.konsole", "0a1b2c3d"),
+            "app-yappla-org.kde.konsole-0a1b2c3d.scope"
+        );
+    }
+
+    #[test]
+    fn unit_name_escaping() {
+        assert_eq!(escape_unit_name("org.gnome.Nautilus"), "org.gnome.Nautilus");
+        assert_eq!(escape_unit_name("my_app:1"), "my_app:1");
+        assert_eq!(escape_unit_name("gnome-terminal"), "gnome\\x2dterminal");
+        assert_eq!(escape_unit_name(".hidden.app"), "\\x2ehidden.app");
+        assert_eq!(escape_unit_name("my app"), "my\\x20app");
+        assert_eq!(escape_unit_name("café"), "caf\\xc3\\xa9");
+    }
+
+    fn env(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn gpu_offload_with_two_gpus() {
+        assert_eq!(gpu_offload_env(&GpuOffload::Prime, 2), env(&[("DRI_PRIME", "1")]));
+        assert_eq!(
+            gpu_offload_env(&GpuOffload::Nvidia, 2),
+            env(&[
+                ("__NV_PRIME_RENDER_OFFLOAD", "1"),
+                ("__VK_LAYER_NV_optimus", "NVIDIA_only"),
+                ("__GLX_VENDOR_LIBRARY_NAME", "nvidia"),
+            ])
+        );
+        assert_eq!(gpu_offload_env(&GpuOffload::Off, 2), env(&[]));
+    }
+
+    #[test]
+    fn gpu_offload_with_own_variables() {
+        let offload = GpuOffload::Env(
+            [("B", "2"), ("A", "1")]
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        );
+
+        assert_eq!(gpu_offload_env(&offload, 3), env(&[("A", "1"), ("B", "2")]));
+    }
+
+    #[test]
+    fn no_gpu_offload_with_one_gpu() {
+        assert_eq!(gpu_offload_env(&GpuOffload::Prime, 1), env(&[]));
+        assert_eq!(gpu_offload_env(&GpuOffload::Nvidia, 1), env(&[]));
+        assert_eq!(gpu_offload_env(&GpuOffload::Prime, 0), env(&[]));
+    }
+
+    #[test]
+    fn object_paths() {
+        assert_eq!(object_path("org.gnome.Nautilus"), "/org/gnome/Nautilus");
+        assert_eq!(object_path("org.gnome.font-viewer"), "/org/gnome/font_viewer");
+    }
+
+    /// The `a{sv}` platform data, the last parameter of both methods.
+    fn platform_data(parameters: &glib::Variant) -> glib::VariantDict {
+        glib::VariantDict::new(Some(&parameters.child_value(parameters.n_children() - 1)))
+    }
+
+    fn token(parameters: &glib::Variant, key: &str) -> Option<String> {
+        platform_data(parameters).lookup(key).unwrap()
+    }
+
+    #[test]
+    fn activate_without_uris() {
+        let (method, parameters) = activation_call(&[], Some("token-1".to_string()));
+
+        assert_eq!(method, "Activate");
+        assert_eq!(parameters.type_().as_str(), "(a{sv})");
+        assert_eq!(token(&parameters, "activation-token").as_deref(), Some("token-1"));
+        assert_eq!(token(&parameters, "desktop-startup-id").as_deref(), Some("token-1"));
+    }
+
+    #[test]
+    fn open_with_uris() {
+        let uris = vec!["file:///tmp/a%20b.txt".to_string(), "https://example.org/".to_string()];
+        let (method, parameters) = activation_call(&uris, None);
+
+        assert_eq!(method, "Open");
+        assert_eq!(parameters.type_().as_str(), "(asa{sv})");
+        assert_eq!(parameters.child_value(0).get::<Vec<String>>(), Some(uris));
+        assert_eq!(parameters.child_value(1).n_children(), 0);
+    }
+
+    /// A private `dbus-daemon`, killed when dropped.
+    struct TestBus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl TestBus {
+        /// `None` when `dbus-daemon` isn't installed.
+        fn start() -> Option<Self> {
+            let mut daemon = match Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+            {
+                Ok(daemon) => daemon,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return None,
+                Err(err) => panic!("failed to start dbus-daemon: {}", err),
+            };
+
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take().unwrap())
+                .read_line(&mut address)
+                .unwrap();
+
+            Some(Self { daemon, address: address.trim().to_string() })
+        }
+    }
+
+    impl Drop for TestBus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    fn connect(address: &str) -> gio::DBusConnection {
+        gio::DBusConnection::for_address_sync(
+            address,
+            gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            None::<&gio::Cancellable>,
+        )
+        .unwrap()
+    }
+
+    const APPLICATION_INTERFACE: &str = r#"
+        <node>
+          <interface name="org.freedesktop.Application">
+            <method name="Activate">
+              <arg type="a{sv}" direction="in"/>
+            </method>
+            <method name="Open">
+              <arg type="as" direction="in"/>
+              <arg type="a{sv}" direction="in"/>
+            </method>
+          </interface>
+        </node>"#;
+
+    /// Owns `app_id` on the bus from a thread of its own, and hands out every
+    /// call to its `org.freedesktop.Application` as `(method, parameters)`.
+    fn fake_app(address: &str, app_id: &'static str) -> mpsc::Receiver<(String, glib::Variant)> {
+        let (calls, received) = mpsc::channel();
+        let (ready, wait_ready) = mpsc::channel();
+        let address = address.to_string();
+
+        thread::spawn(move || {
+            let context = glib::MainContext::new();
+
+            context
+                .with_thread_default(|| {
+                    let connection = connect(&address);
+                    let node = gio::DBusNodeInfo::for_xml(APPLICATION_INTERFACE).unwrap();
+                    let interface = node.lookup_interface("org.freedesktop.Application").unwrap();
+
+                    connection
+                        .register_object(&object_path(app_id), &interface)
+                        .method_call(move |_, _, _, _, method, parameters, invocation| {
+                            let _ = calls.send((method.to_string(), parameters));
+                            invocation.return_value(None);
+                        })
+                        .build()
+                        .unwrap();
+
+                    connection
+                        .call_sync(
+                            Some("org.freedesktop.DBus"),
+                            "/org/freedesktop/DBus",
+                            "org.freedesktop.DBus",
+                            "RequestName",
+                            Some(&(app_id, 0u32).to_variant()),
+                            None,
+                            gio::DBusCallFlags::NONE,
+                            -1,
+                            None::<&gio::Cancellable>,
+                        )
+                        .unwrap();
+
+                    ready.send(()).unwrap();
+                    glib::MainLoop::new(Some(&context), false).run();
+                })
+                .unwrap();
+        });
+
+        wait_ready.recv().unwrap();
+        received
+    }
+
+    #[test]
+    fn activation_over_dbus() {
+        let Some(bus) = TestBus::start() else {
+            eprintln!("dbus-daemon isn't installed, skipping");
+            return;
+        };
+
+        let app_id = "org.example.Fake-App";
+        let calls = fake_app(&bus.address, app_id);
+        let connection = connect(&bus.address);
+        let next_call = || calls.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        call_activation(&connection, app_id, &[], Some("token-1".to_string())).unwrap();
+        let (method, parameters) = next_call();
+        assert_eq!(method, "Activate");
+        assert_eq!(token(&parameters, "activation-token").as_deref(), Some("token-1"));
+
+        let uris = vec!["file:///tmp/a.txt".to_string()];
+        call_activation(&connection, app_id, &uris, None).unwrap();
+        let (method, parameters) = next_call();
+        assert_eq!(method, "Open");
+        assert_eq!(parameters.child_value(0).get::<Vec<String>>(), Some(uris));
+        assert_eq!(token(&parameters, "activation-token"), None);
+
+        // Nobody owns the name, which makes yappla fall back to `Exec`.
+        assert!(call_activation(&connection, "org.example.Missing", &[], None).is_err());
+    }
+}
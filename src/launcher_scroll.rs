@@ -6,18 +6,23 @@ use glib::{
 };
 use gtk::{ListScrollFlags, ScrollInfo, ScrolledWindow, prelude::AdjustmentExt};
 use relm4::{
-    RelmIterChildrenExt,
     gtk::{
         self, ListItem, NoSelection, SignalListItemFactory,
         gio::prelude::{ListModelExt, ListModelExtManual},
-        prelude::{ListItemExt, WidgetExt},
+        prelude::{ListItemExt, OrientableExt, WidgetExt},
     },
     view,
 };
 
 use crate::{
+    cli,
+    config::Config,
+    icons::IconCache,
     index_list::Index,
-    modes::{apps_mode::AppsMode, echo_mode::EchoMode, mode::Mode},
+    modes::{
+        apps_mode::AppsMode, echo_mode::EchoMode, games_mode::GamesMode,
+        history_mode::HistoryMode, mode::Mode, open_with_mode::OpenWithMode,
+    },
     scroll::{ScrollBox, ScrollComponent, ScrollComponentImpl, ScrollSettings},
 };
 
@@ -27,11 +32,15 @@ pub enum ScrollListMessages {
     MoveDown,
     MoveUp,
     Enter,
+    TogglePinned,
+    ToggleHidden,
 }
 
 pub struct LauncherScrollImpl {
     focused: RefCell<Option<u32>>,
+    query: RefCell<String>,
     mode: Box<dyn Mode>,
+    icons: IconCache,
 }
 
 impl LauncherScrollImpl {
@@ -101,13 +110,37 @@ impl ScrollComponentImpl<ScrollComponent<Self, ScrollListMessages>, ScrollListMe
                 set_height_request: 20,
                 set_margin_top: 0,
                 set_margin_bottom: 0,
-                #[name = "label"]
+                #[name = "icon"]
+                gtk::Image {
+                    add_css_class: "icon",
+                },
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_valign: gtk::Align::Center,
+                    set_hexpand: true,
+
+                    #[name = "label"]
+                    gtk::Label {
+                        set_xalign: 0.0,
+                    },
+
+                    gtk::Label {
+                        add_css_class: "description",
+                        set_xalign: 0.0,
+                        set_ellipsize: gtk::pango::EllipsizeMode::End,
+                    }
+                },
                 gtk::Label {
-
+                    add_css_class: "tag",
+                    set_valign: gtk::Align::Center,
                 }
             }
         };
 
+        if let Some(size) = Config::get().icon_size {
+            icon.set_pixel_size(size);
+        }
+
         let this = this.downgrade();
         let gtk_clone = gtk_box.downgrade();
 
@@ -115,11 +148,19 @@ impl ScrollComponentImpl<ScrollComponent<Self, ScrollListMessages>, ScrollListMe
             let this = this.upgrade().unwrap();
             let gtk_box = gtk_clone.upgrade().unwrap();
 
-            if let Some(focused) = this.focused.borrow().as_ref() {
-                if gtk_box.index() == *focused {
-                    let index = Index::new(gtk_box.index() as u32);
-                    this.mode.get_menu_item_model(&index).run();
-                }
+            let focused = *this.focused.borrow();
+
+            if focused == Some(gtk_box.index())
+                && let Some(index) = this.mode.model().item(gtk_box.index()).and_downcast::<Index>()
+                && this.mode.activate(&index)
+            {
+                // The model is shared with the list view, so searching again
+                // updates the rows.
+                *this.focused.borrow_mut() = None;
+                let query = this.query.borrow().clone();
+                this.mode.search(query);
+                this.set_focus(0);
+                return;
             }
 
             this.set_focus(gtk_box.index());
@@ -132,9 +173,25 @@ impl ScrollComponentImpl<ScrollComponent<Self, ScrollListMessages>, ScrollListMe
         let gtk_box = item.child().unwrap().downcast::<gtk::Box>().unwrap();
         let scroll_box = gtk_box.clone().downcast::<ScrollBox>().unwrap();
         let index = item.item().unwrap().downcast::<Index>().unwrap();
-        let label = scroll_box
+        let icon = scroll_box
             .first_child()
             .unwrap()
+            .downcast::<gtk::Image>()
+            .unwrap();
+        let text_box = icon.next_sibling().unwrap();
+        let label = text_box
+            .first_child()
+            .unwrap()
+            .downcast::<gtk::Label>()
+            .unwrap();
+        let description = label
+            .next_sibling()
+            .unwrap()
+            .downcast::<gtk::Label>()
+            .unwrap();
+        let tag = text_box
+            .next_sibling()
+            .unwrap()
             .downcast::<gtk::Label>()
             .unwrap();
 
@@ -155,26 +212,63 @@ impl ScrollComponentImpl<ScrollComponent<Self, ScrollListMessages>, ScrollListMe
             label.add_css_class("text")
         }
 
-        gtk_box
-            .iter_children()
-            .nth(0)
-            .unwrap()
-            .downcast::<gtk::Label>()
-            .unwrap()
-            .set_text(this.mode.get_menu_item_model(&index).name());
+        let menu_item = this.mode.get_menu_item_model(&index);
+
+        match this.mode.highlight(&index, &this.query.borrow()) {
+            Some(positions) => label.set_markup(&bold(menu_item.name(), &positions)),
+            None => label.set_text(menu_item.name()),
+        }
+
+        match menu_item.description() {
+            Some(text) => {
+                description.set_text(text);
+                description.set_visible(true);
+            }
+            None => description.set_visible(false),
+        }
+
+        match menu_item.tag() {
+            Some(text) => {
+                tag.set_text(text);
+                tag.set_visible(true);
+            }
+            None => tag.set_visible(false),
+        }
+
+        match menu_item.icon() {
+            Some(name) => {
+                icon.set_from_gicon(&this.icons.get(name));
+                icon.set_visible(true);
+            }
+            None => icon.set_visible(false),
+        }
     }
 
     fn init() -> Self {
-        let mode = std::env::args().nth(1).unwrap_or(String::new());
+        let mode = cli::mode();
 
         let mode: Box<dyn Mode> = match mode.as_str() {
             "echo" => Box::from(EchoMode::new()),
             "apps" => Box::from(AppsMode::new()),
+            "history" => Box::from(HistoryMode::new(cli::has_flag("--exec"))),
+            "games" => Box::from(GamesMode::new()),
+            "open-with" => match cli::argument() {
+                Some(file) => Box::from(OpenWithMode::new(&file)),
+                None => {
+                    eprintln!("Usage:");
+                    eprintln!("  yappla open-with <file or URL>");
+
+                    std::process::exit(-1);
+                }
+            },
             _ => {
                 eprintln!("Error: unknown mode '{}'.", mode);
                 eprintln!("Available modes:");
-                eprintln!("  apps   Launch application mode");
-                eprintln!("  echo   Echo input back to stdout");
+                eprintln!("  apps     Launch application mode");
+                eprintln!("  echo     Echo input back to stdout");
+                eprintln!("  history  Search shell history (--exec runs it in a terminal)");
+                eprintln!("  games    Launch installed Steam games");
+                eprintln!("  open-with Open a file or URL with one of its apps");
                 eprintln!();
                 eprintln!("Usage:");
                 eprintln!("  yappla <mode>");
@@ -185,7 +279,9 @@ impl ScrollComponentImpl<ScrollComponent<Self, ScrollListMessages>, ScrollListMe
 
         Self {
             focused: Default::default(),
+            query: Default::default(),
             mode,
+            icons: IconCache::default(),
         }
     }
 
@@ -198,6 +294,7 @@ impl ScrollComponentImpl<ScrollComponent<Self, ScrollListMessages>, ScrollListMe
         match msg {
             ScrollListMessages::Query(string) => {
                 *self.focused.borrow_mut() = None;
+                self.query.replace(string.clone());
                 scroll.selection = NoSelection::new(Some(self.mode.search(string)));
                 self.set_focus_with_scroll(0, 0, &scroll.list_view.as_ref().unwrap());
             }
@@ -219,10 +316,62 @@ impl ScrollComponentImpl<ScrollComponent<Self, ScrollListMessages>, ScrollListMe
                     .item(*self.focused.borrow().as_ref().unwrap_or(&0))
                     .and_downcast::<Index>();
 
-                if let Some(index) = index {
-                    self.mode.get_menu_item_model(&index).run();
+                if let Some(index) = index
+                    && self.mode.activate(&index)
+                {
+                    self.refresh(scroll, 0);
                 }
             }
+            ScrollListMessages::TogglePinned => {
+                self.toggle_focused(scroll, |mode, index| mode.toggle_pinned(index));
+            }
+            ScrollListMessages::ToggleHidden => {
+                self.toggle_focused(scroll, |mode, index| mode.toggle_hidden(index));
+            }
         }
     }
 }
+
+impl LauncherScrollImpl {
+    /// Runs `toggle` on the focused item and searches again if that changed
+    /// the list, keeping the focus where it was.
+    fn toggle_focused(
+        &self,
+        scroll: &mut ScrollComponent<Self, ScrollListMessages>,
+        toggle: impl Fn(&dyn Mode, &Index) -> bool,
+    ) {
+        let focused = self.focused.borrow().unwrap_or(0);
+        let index = self.mode.model().item(focused).and_downcast::<Index>();
+
+        if let Some(index) = index
+            && toggle(self.mode.as_ref(), &index)
+        {
+            self.refresh(scroll, focused);
+        }
+    }
+
+    /// Searches again with the current query and focuses `focused`.
+    fn refresh(&self, scroll: &mut ScrollComponent<Self, ScrollListMessages>, focused: u32) {
+        *self.focused.borrow_mut() = None;
+        let query = self.query.borrow().clone();
+        scroll.selection = NoSelection::new(Some(self.mode.search(query)));
+        self.set_focus_with_scroll(focused, 0, scroll.list_view.as_ref().unwrap());
+    }
+}
+
+/// Pango markup of `name` with the chars at `positions` in bold.
+fn bold(name: &str, positions: &[usize]) -> String {
+    let mut markup = String::with_capacity(name.len() * 2);
+
+    for (i, char) in name.chars().enumerate() {
+        let escaped = glib::markup_escape_text(char.encode_utf8(&mut [0; 4]));
+
+        if positions.contains(&i) {
+            markup.push_str(&format!("<b>{}</b>", escaped));
+        } else {
+            markup.push_str(&escaped);
+        }
+    }
+
+    markup
+}
@@ -0,0 +1,64 @@
+use std::env;
+
+use freedesktop_file_parser::{LocaleString, LocaleStringList};
+
+/// Returns the `LC_MESSAGES` locale keys to look up in desktop entries, from
+/// the most specific to the least specific one. Follows the matching rules of
+/// the Desktop Entry spec: for `lang_COUNTRY.ENCODING@MODIFIER` it tries
+/// `lang_COUNTRY@MODIFIER`, `lang_COUNTRY`, `lang@MODIFIER` and `lang`.
+pub fn message_locales() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|it| env::var(it).ok())
+        .find(|it| !it.is_empty());
+
+    match locale {
+        Some(locale) if locale != "C" && locale != "POSIX" => candidates(&locale),
+        _ => Vec::new(),
+    }
+}
+
+fn candidates(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+
+    let locale = locale.split('.').next().unwrap_or(locale);
+
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut candidates = Vec::with_capacity(4);
+
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        candidates.push(format!("{}_{}@{}", lang, country, modifier));
+    }
+
+    if let Some(country) = country {
+        candidates.push(format!("{}_{}", lang, country));
+    }
+
+    if let Some(modifier) = modifier {
+        candidates.push(format!("{}@{}", lang, modifier));
+    }
+
+    candidates.push(lang.to_string());
+    candidates
+}
+
+pub fn localized<'a>(string: &'a LocaleString, locales: &[String]) -> &'a str {
+    locales
+        .iter()
+        .find_map(|it| string.variants.get(it))
+        .unwrap_or(&string.default)
+}
+
+pub fn localized_list<'a>(list: &'a LocaleStringList, locales: &[String]) -> &'a [String] {
+    locales
+        .iter()
+        .find_map(|it| list.variants.get(it))
+        .unwrap_or(&list.default)
+}
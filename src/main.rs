@@ -1,9 +1,22 @@
+mod appimage;
+mod cli;
+mod config;
+mod exec;
+mod frecency;
+mod icons;
 mod index_list;
+mod launch;
 mod launcher_scroll;
+mod locale;
+mod menu;
 mod menu_item_model;
+mod mimeapps;
 mod modes;
 mod scroll;
+mod terminal;
 pub mod search;
+mod vdf;
+mod xml;
 
 use crate::launcher_scroll::*;
 use crate::scroll::ScrollComponent;
@@ -23,6 +36,8 @@ enum AppMsg {
     MoveDown,
     MoveUp,
     Enter,
+    TogglePinned,
+    ToggleHidden,
 }
 
 struct App {
@@ -133,19 +148,30 @@ impl SimpleComponent for App {
 
         let clonned_sender = _sender.clone();
 
-        key_controller.connect_key_pressed(move |_controller, keyval, _keycode, _| match keyval {
-            gdk::Key::Escape => {
-                std::process::exit(0);
+        key_controller.connect_key_pressed(move |_controller, keyval, _keycode, state| {
+            // Shift and Caps Lock turn `p` into `P`.
+            match keyval.to_lower() {
+                gdk::Key::p if state.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    clonned_sender.input(AppMsg::TogglePinned);
+                    glib::Propagation::Stop
+                }
+                gdk::Key::h if state.contains(gdk::ModifierType::CONTROL_MASK) => {
+                    clonned_sender.input(AppMsg::ToggleHidden);
+                    glib::Propagation::Stop
+                }
+                gdk::Key::Escape => {
+                    std::process::exit(0);
+                }
+                gdk::Key::Up => {
+                    clonned_sender.input(AppMsg::MoveUp);
+                    glib::Propagation::Stop
+                }
+                gdk::Key::Down => {
+                    clonned_sender.input(AppMsg::MoveDown);
+                    glib::Propagation::Stop
+                }
+                _ => glib::Propagation::Proceed,
             }
-            gdk::Key::Up => {
-                clonned_sender.input(AppMsg::MoveUp);
-                glib::Propagation::Stop
-            }
-            gdk::Key::Down => {
-                clonned_sender.input(AppMsg::MoveDown);
-                glib::Propagation::Stop
-            }
-            _ => glib::Propagation::Proceed,
         });
 
         
@@ -160,11 +186,15 @@ impl SimpleComponent for App {
             AppMsg::MoveDown => self.scroll.sender().emit(ScrollListMessages::MoveDown),
             AppMsg::MoveUp => self.scroll.sender().emit(ScrollListMessages::MoveUp),
             AppMsg::Enter => self.scroll.sender().emit(ScrollListMessages::Enter),
+            AppMsg::TogglePinned => self.scroll.sender().emit(ScrollListMessages::TogglePinned),
+            AppMsg::ToggleHidden => self.scroll.sender().emit(ScrollListMessages::ToggleHidden),
         }
     }
 }
 
 fn main() {
+    launch::capture_environment();
+
     RelmApp::new("com.intbyte.yappla")
         .with_args(Vec::default())
         .run::<App>(0);
@@ -0,0 +1,621 @@
+use std::{
+    collections::HashSet,
+    env, fs, mem,
+    path::{Path, PathBuf},
+};
+
+use freedesktop_file_parser::parse;
+
+use crate::{
+    cli, locale,
+    xml::{self, Element},
+};
+
+/// Categories of the Desktop Menu spec every app should have one of, used
+/// when there is no `applications.menu`: category, name and icon.
+const MAIN_CATEGORIES: [(&str, &str, &str); 11] = [
+    ("AudioVideo", "Multimedia", "applications-multimedia"),
+    ("Development", "Development", "applications-development"),
+    ("Education", "Education", "applications-education"),
+    ("Game", "Games", "applications-games"),
+    ("Graphics", "Graphics", "applications-graphics"),
+    ("Network", "Internet", "applications-internet"),
+    ("Office", "Office", "applications-office"),
+    ("Science", "Science", "applications-science"),
+    ("Settings", "Settings", "preferences-system"),
+    ("System", "System", "applications-system"),
+    ("Utility", "Accessories", "applications-utilities"),
+];
+
+/// A submenu with its apps, which are indices into the apps given to
+/// `load`. Neither is sorted.
+pub struct Menu {
+    pub name: String,
+    pub icon: Option<String>,
+    pub comment: Option<String>,
+    pub menus: Vec<Menu>,
+    pub apps: Vec<usize>,
+}
+
+/// What the menu rules look at.
+pub struct MenuApp<'a> {
+    /// Desktop-file ID, matched by `<Filename>`.
+    pub id: &'a str,
+    pub categories: &'a [String],
+}
+
+/// Builds the menu from `applications.menu` as the XDG Desktop Menu spec
+/// describes it, or from the main categories when there is none. `AppDir`,
+/// `LegacyDir`, `Move` and `Layout` are ignored: the apps are the ones yappla
+/// found itself, and menus are sorted by name.
+pub fn load(apps: &[MenuApp], locales: &[String]) -> Menu {
+    let path = match find_menu_file() {
+        Some(path) => path,
+        None => return fallback(apps),
+    };
+
+    let mut raw = RawMenu::default();
+
+    if let Err(err) = read_file(&path, &mut raw, &mut HashSet::new()) {
+        eprintln!("Failed to read {}: {}", path.display(), err);
+        return fallback(apps);
+    }
+
+    let mut allocated = HashSet::new();
+    raw.allocate(apps, false, &mut allocated);
+    raw.allocate(apps, true, &mut allocated);
+
+    match raw.finish(&[], locales) {
+        Some(menu) => menu,
+        None => {
+            if cli::has_flag("--debug") {
+                eprintln!("{} has no apps, using the main categories", path.display());
+            }
+
+            fallback(apps)
+        }
+    }
+}
+
+/// A `<Menu>` after merging, before its `.directory` file is read.
+#[derive(Default)]
+struct RawMenu {
+    name: String,
+    directories: Vec<String>,
+    directory_dirs: Vec<PathBuf>,
+    /// `<Include>` (true) and `<Exclude>` rules, in order. The last rule
+    /// matching an app decides.
+    rules: Vec<(bool, Rule)>,
+    only_unallocated: Option<bool>,
+    deleted: Option<bool>,
+    menus: Vec<RawMenu>,
+    apps: Vec<usize>,
+}
+
+enum Rule {
+    Filename(String),
+    Category(String),
+    All,
+    And(Vec<Rule>),
+    Or(Vec<Rule>),
+    Not(Vec<Rule>),
+}
+
+impl Rule {
+    fn matches(&self, app: &MenuApp) -> bool {
+        match self {
+            Rule::Filename(id) => app.id == id,
+            Rule::Category(category) => app.categories.contains(category),
+            Rule::All => true,
+            Rule::And(rules) => rules.iter().all(|it| it.matches(app)),
+            Rule::Or(rules) => rules.iter().any(|it| it.matches(app)),
+            Rule::Not(rules) => !rules.iter().any(|it| it.matches(app)),
+        }
+    }
+
+    fn read_all(element: &Element) -> Vec<Rule> {
+        element
+            .elements()
+            .filter_map(|child| match child.name.as_str() {
+                "Filename" => Some(Rule::Filename(child.text())),
+                "Category" => Some(Rule::Category(child.text())),
+                "All" => Some(Rule::All),
+                "And" => Some(Rule::And(Self::read_all(child))),
+                "Or" => Some(Rule::Or(Self::read_all(child))),
+                "Not" => Some(Rule::Not(Self::read_all(child))),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl RawMenu {
+    fn read(&mut self, element: &Element, base: &Path, visited: &mut HashSet<PathBuf>) {
+        for child in element.elements() {
+            match child.name.as_str() {
+                "Name" => self.name = child.text(),
+                "Directory" => self.directories.push(child.text()),
+                "DirectoryDir" => self.directory_dirs.push(base.join(child.text())),
+                "DefaultDirectoryDirs" => self.directory_dirs.extend(default_directory_dirs()),
+                "OnlyUnallocated" => self.only_unallocated = Some(true),
+                "NotOnlyUnallocated" => self.only_unallocated = Some(false),
+                "Deleted" => self.deleted = Some(true),
+                "NotDeleted" => self.deleted = Some(false),
+                "Include" => self.rules.push((true, Rule::Or(Rule::read_all(child)))),
+                "Exclude" => self.rules.push((false, Rule::Or(Rule::read_all(child)))),
+                "Menu" => {
+                    let mut menu = RawMenu::default();
+                    menu.read(child, base, visited);
+                    self.add_menu(menu);
+                }
+                // `type="parent"` merges the menu this one replaces, which
+                // isn't supported.
+                "MergeFile" if child.attribute("type") != Some("parent") => {
+                    let path = base.join(child.text());
+
+                    if let Err(err) = read_file(&path, self, visited) {
+                        eprintln!("Failed to merge {}: {}", path.display(), err);
+                    }
+                }
+                "MergeDir" => merge_dir(&base.join(child.text()), self, visited),
+                "DefaultMergeDirs" => {
+                    for dir in default_merge_dirs() {
+                        merge_dir(&dir, self, visited);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Menus with the same name are merged, later content wins.
+    fn add_menu(&mut self, menu: RawMenu) {
+        let existing = match self.menus.iter_mut().find(|it| it.name == menu.name) {
+            Some(existing) => existing,
+            None => {
+                self.menus.push(menu);
+                return;
+            }
+        };
+
+        existing.directories.extend(menu.directories);
+        existing.directory_dirs.extend(menu.directory_dirs);
+        existing.rules.extend(menu.rules);
+        existing.only_unallocated = menu.only_unallocated.or(existing.only_unallocated);
+        existing.deleted = menu.deleted.or(existing.deleted);
+
+        for child in menu.menus {
+            existing.add_menu(child);
+        }
+    }
+
+    /// Picks the apps of this menu and its submenus. Menus with
+    /// `<OnlyUnallocated>` run in a second pass and only get apps no other
+    /// menu took.
+    fn allocate(
+        &mut self,
+        apps: &[MenuApp],
+        unallocated_pass: bool,
+        allocated: &mut HashSet<usize>,
+    ) {
+        if self.only_unallocated.unwrap_or(false) == unallocated_pass {
+            self.apps = (0..apps.len())
+                .filter(|i| !unallocated_pass || !allocated.contains(i))
+                .filter(|i| {
+                    self.rules
+                        .iter()
+                        .rev()
+                        .find(|(_, rule)| rule.matches(&apps[*i]))
+                        .is_some_and(|(include, _)| *include)
+                })
+                .collect();
+
+            if !unallocated_pass {
+                allocated.extend(&self.apps);
+            }
+        }
+
+        for menu in &mut self.menus {
+            menu.allocate(apps, unallocated_pass, allocated);
+        }
+    }
+
+    /// Reads the `.directory` file and drops deleted, hidden and empty
+    /// menus. `DirectoryDir`s are inherited by submenus.
+    fn finish(self, inherited_dirs: &[PathBuf], locales: &[String]) -> Option<Menu> {
+        if self.deleted.unwrap_or(false) {
+            return None;
+        }
+
+        let dirs: Vec<PathBuf> = inherited_dirs
+            .iter()
+            .chain(&self.directory_dirs)
+            .cloned()
+            .collect();
+
+        let directory = find_directory(&self.directories, &dirs, locales);
+
+        if directory.as_ref().is_some_and(|it| it.no_display) {
+            return None;
+        }
+
+        let menus: Vec<Menu> = self
+            .menus
+            .into_iter()
+            .filter_map(|it| it.finish(&dirs, locales))
+            .collect();
+
+        if menus.is_empty() && self.apps.is_empty() {
+            return None;
+        }
+
+        let (name, icon, comment) = match directory {
+            Some(directory) => (directory.name, directory.icon, directory.comment),
+            None => (self.name, None, None),
+        };
+
+        Some(Menu {
+            name,
+            icon,
+            comment,
+            menus,
+            apps: self.apps,
+        })
+    }
+}
+
+/// Reads a menu file into `menu`. The `<Name>` of a merged file's root menu
+/// is ignored, its content goes into the menu that merges it.
+fn read_file(
+    path: &Path,
+    menu: &mut RawMenu,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<(), String> {
+    let canonical = fs::canonicalize(path).map_err(|it| it.to_string())?;
+
+    // Merges that loop back are skipped.
+    if !visited.insert(canonical) {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(path).map_err(|it| it.to_string())?;
+    let root = xml::parse(&content).map_err(|it| it.to_string())?;
+
+    read_root(&root, path.parent().unwrap_or(Path::new("/")), menu, visited)
+}
+
+/// Reads the root element of a menu file into `menu`. Relative paths are
+/// resolved against `base`, the directory of the file.
+fn read_root(
+    root: &Element,
+    base: &Path,
+    menu: &mut RawMenu,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<(), String> {
+    if root.name != "Menu" {
+        return Err(format!("the root element is <{}> instead of <Menu>", root.name));
+    }
+
+    let name = mem::take(&mut menu.name);
+    menu.read(root, base, visited);
+
+    if !name.is_empty() {
+        menu.name = name;
+    }
+
+    Ok(())
+}
+
+fn merge_dir(dir: &Path, menu: &mut RawMenu, visited: &mut HashSet<PathBuf>) {
+    let mut files: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|it| it.path())
+            .filter(|it| it.extension().and_then(|it| it.to_str()) == Some("menu"))
+            .collect(),
+        Err(_) => return,
+    };
+
+    files.sort();
+
+    for path in files {
+        if let Err(err) = read_file(&path, menu, visited) {
+            eprintln!("Failed to merge {}: {}", path.display(), err);
+        }
+    }
+}
+
+struct DirectoryInfo {
+    name: String,
+    icon: Option<String>,
+    comment: Option<String>,
+    no_display: bool,
+}
+
+/// Reads the last `<Directory>` that exists, looking through the
+/// `DirectoryDir`s from the last one.
+fn find_directory(names: &[String], dirs: &[PathBuf], locales: &[String]) -> Option<DirectoryInfo> {
+    for name in names.iter().rev() {
+        for dir in dirs.iter().rev() {
+            let desktop = match fs::read_to_string(dir.join(name)).map(|it| parse(&it)) {
+                Ok(Ok(desktop)) => desktop,
+                _ => continue,
+            };
+
+            let entry = desktop.entry;
+
+            return Some(DirectoryInfo {
+                name: locale::localized(&entry.name, locales).to_string(),
+                icon: entry.icon.map(|it| it.content),
+                comment: entry
+                    .comment
+                    .as_ref()
+                    .map(|it| locale::localized(it, locales).to_string()),
+                no_display: entry.no_display.unwrap_or(false) || entry.hidden.unwrap_or(false),
+            });
+        }
+    }
+
+    None
+}
+
+/// One menu per main category, plus "Other" for apps without one.
+fn fallback(apps: &[MenuApp]) -> Menu {
+    let mut menus: Vec<Menu> = MAIN_CATEGORIES
+        .iter()
+        .map(|(category, name, icon)| Menu {
+            name: name.to_string(),
+            icon: Some(icon.to_string()),
+            comment: None,
+            menus: Vec::new(),
+            apps: (0..apps.len())
+                .filter(|i| apps[*i].categories.iter().any(|it| it == category))
+                .collect(),
+        })
+        .collect();
+
+    menus.push(Menu {
+        name: "Other".to_string(),
+        icon: Some("applications-other".to_string()),
+        comment: None,
+        menus: Vec::new(),
+        apps: (0..apps.len())
+            .filter(|i| {
+                !apps[*i]
+                    .categories
+                    .iter()
+                    .any(|it| MAIN_CATEGORIES.iter().any(|(main, _, _)| it == main))
+            })
+            .collect(),
+    });
+
+    menus.retain(|it| !it.apps.is_empty());
+
+    Menu {
+        name: "Applications".to_string(),
+        icon: None,
+        comment: None,
+        menus,
+        apps: Vec::new(),
+    }
+}
+
+fn config_dirs() -> Vec<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|it| it.join(".config")));
+    let config_dirs = env::var_os("XDG_CONFIG_DIRS").unwrap_or_else(|| "/etc/xdg".into());
+
+    config_home
+        .into_iter()
+        .chain(env::split_paths(&config_dirs))
+        .collect()
+}
+
+/// `$XDG_MENU_PREFIX` lets a desktop pick its own menu, like
+/// `gnome-applications.menu`.
+fn find_menu_file() -> Option<PathBuf> {
+    let prefix = env::var("XDG_MENU_PREFIX").unwrap_or_default();
+
+    [format!("{}applications.menu", prefix), "applications.menu".to_string()]
+        .iter()
+        .flat_map(|name| config_dirs().into_iter().map(move |dir| dir.join("menus").join(name)))
+        .find(|it| it.is_file())
+}
+
+/// From the least to the most important, as later merges win.
+fn default_merge_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = config_dirs()
+        .into_iter()
+        .map(|it| it.join("menus/applications-merged"))
+        .collect();
+
+    dirs.reverse();
+    dirs
+}
+
+/// From the least to the most important, as later directories are searched
+/// first.
+fn default_directory_dirs() -> Vec<PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::home_dir().map(|it| it.join(".local/share")));
+    let data_dirs =
+        env::var_os("XDG_DATA_DIRS").unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+
+    let mut dirs: Vec<PathBuf> = data_home
+        .into_iter()
+        .chain(env::split_paths(&data_dirs))
+        .map(|it| it.join("desktop-directories"))
+        .collect();
+
+    dirs.reverse();
+    dirs
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::Path};
+
+    use super::{MenuApp, RawMenu, read_root};
+    use crate::xml;
+
+    /// `(desktop-file ID, categories)`.
+    const APPS: [(&str, &[&str]); 4] = [
+        ("editor.desktop", &["Development", "TextEditor"]),
+        ("ide.desktop", &["Development", "IDE"]),
+        ("player.desktop", &["AudioVideo", "Player"]),
+        ("tool.desktop", &[]),
+    ];
+
+    /// Reads `content` as a menu file and allocates `APPS` like `load` does.
+    fn allocate(content: &str) -> RawMenu {
+        let categories: Vec<Vec<String>> = APPS
+            .iter()
+            .map(|(_, categories)| categories.iter().map(|it| it.to_string()).collect())
+            .collect();
+        let apps: Vec<MenuApp> = APPS
+            .iter()
+            .zip(&categories)
+            .map(|((id, _), categories)| MenuApp { id, categories })
+            .collect();
+
+        let root = xml::parse(content).unwrap();
+        let mut menu = RawMenu::default();
+        read_root(&root, Path::new("/"), &mut menu, &mut HashSet::new()).unwrap();
+
+        let mut allocated = HashSet::new();
+        menu.allocate(&apps, false, &mut allocated);
+        menu.allocate(&apps, true, &mut allocated);
+        menu
+    }
+
+    /// The IDs of the apps of the submenu `name`.
+    fn apps(menu: &RawMenu, name: &str) -> Vec<&'static str> {
+        let menu = menu.menus.iter().find(|it| it.name == name).unwrap();
+        menu.apps.iter().map(|it| APPS[*it].0).collect()
+    }
+
+    #[test]
+    fn last_matching_rule_wins() {
+        let menu = allocate(
+            "<Menu><Name>Applications</Name>
+               <Menu><Name>Dev</Name>
+                 <Include><Category>Development</Category></Include>
+                 <Exclude><Category>IDE</Category></Exclude>
+                 <Include><Filename>ide.desktop</Filename></Include>
+                 <Exclude><Filename>editor.desktop</Filename></Exclude>
+               </Menu>
+             </Menu>",
+        );
+
+        assert_eq!(apps(&menu, "Dev"), ["ide.desktop"]);
+    }
+
+    #[test]
+    fn and_or_not() {
+        let menu = allocate(
+            "<Menu><Name>Applications</Name>
+               <Menu><Name>And</Name>
+                 <Include>
+                   <And><Category>Development</Category><Category>IDE</Category></And>
+                 </Include>
+               </Menu>
+               <Menu><Name>Or</Name>
+                 <Include><Or><Category>IDE</Category><Category>Player</Category></Or></Include>
+               </Menu>
+               <Menu><Name>Not</Name>
+                 <Include>
+                   <Not><Category>Development</Category><Category>Player</Category></Not>
+                 </Include>
+               </Menu>
+               <Menu><Name>All</Name>
+                 <Include><All/></Include>
+                 <Exclude><Not><Filename>tool.desktop</Filename></Not></Exclude>
+               </Menu>
+             </Menu>",
+        );
+
+        assert_eq!(apps(&menu, "And"), ["ide.desktop"]);
+        assert_eq!(apps(&menu, "Or"), ["ide.desktop", "player.desktop"]);
+        assert_eq!(apps(&menu, "Not"), ["tool.desktop"]);
+        assert_eq!(apps(&menu, "All"), ["tool.desktop"]);
+    }
+
+    #[test]
+    fn only_unallocated_runs_last() {
+        let menu = allocate(
+            "<Menu><Name>Applications</Name>
+               <Menu><Name>Other</Name>
+                 <OnlyUnallocated/>
+                 <Include><All/></Include>
+               </Menu>
+               <Menu><Name>Dev</Name>
+                 <Include><Category>Development</Category></Include>
+               </Menu>
+               <Menu><Name>Also Dev</Name>
+                 <Include><Filename>editor.desktop</Filename></Include>
+               </Menu>
+             </Menu>",
+        );
+
+        assert_eq!(apps(&menu, "Other"), ["player.desktop", "tool.desktop"]);
+        assert_eq!(apps(&menu, "Dev"), ["editor.desktop", "ide.desktop"]);
+        assert_eq!(apps(&menu, "Also Dev"), ["editor.desktop"]);
+    }
+
+    #[test]
+    fn menus_with_the_same_name_are_merged() {
+        let menu = allocate(
+            "<Menu><Name>Applications</Name>
+               <Menu><Name>Media</Name>
+                 <Include><Category>Player</Category></Include>
+                 <Deleted/>
+                 <Menu><Name>Sub</Name><Directory>a.directory</Directory></Menu>
+               </Menu>
+               <Menu><Name>Media</Name>
+                 <Include><Filename>tool.desktop</Filename></Include>
+                 <NotDeleted/>
+                 <Menu><Name>Sub</Name><Directory>b.directory</Directory></Menu>
+               </Menu>
+             </Menu>",
+        );
+
+        assert_eq!(menu.menus.len(), 1);
+        assert_eq!(apps(&menu, "Media"), ["player.desktop", "tool.desktop"]);
+
+        let media = &menu.menus[0];
+        assert_eq!(media.deleted, Some(false));
+        assert_eq!(media.menus.len(), 1);
+        assert_eq!(media.menus[0].directories, ["a.directory", "b.directory"]);
+    }
+
+    #[test]
+    fn deleted_menus_are_dropped() {
+        let menu = allocate(
+            "<Menu><Name>Applications</Name>
+               <Menu><Name>Kept</Name>
+                 <Deleted/><NotDeleted/>
+                 <Include><All/></Include>
+               </Menu>
+               <Menu><Name>Gone</Name>
+                 <NotDeleted/><Deleted/>
+                 <Include><All/></Include>
+               </Menu>
+             </Menu>",
+        );
+
+        let menu = menu.finish(&[], &[]).unwrap();
+        let names: Vec<&str> = menu.menus.iter().map(|it| it.name.as_str()).collect();
+
+        assert_eq!(names, ["Kept"]);
+    }
+
+    #[test]
+    fn root_must_be_a_menu() {
+        let root = xml::parse("<Other/>").unwrap();
+        let result = read_root(&root, Path::new("/"), &mut RawMenu::default(), &mut HashSet::new());
+
+        assert!(result.is_err());
+    }
+}
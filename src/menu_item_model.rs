@@ -1,16 +1,49 @@
 use std::fmt::Display;
 
+use crate::search;
+
 pub trait MenuItemModel {
     fn name<'a>(&'a self) -> &'a String;
     fn run_action(&self) -> Result<(), ActionError>;
+
+    /// Icon name or absolute path, as in the `Icon` key of desktop entries.
+    fn icon(&self) -> Option<&str> {
+        None
+    }
+
+    /// Secondary line shown under the name.
+    fn description(&self) -> Option<&str> {
+        None
+    }
+
+    /// Short label at the end of the row, like where an app comes from.
+    fn tag(&self) -> Option<&str> {
+        None
+    }
+
+    /// Chars of the name matched by the lower case `query`, shown in bold.
+    /// Items found by more than their name return `None` when something
+    /// else matched.
+    fn highlight(&self, query: &str) -> Option<Vec<usize>> {
+        search::positions(self.name(), query)
+    }
     
     fn run(&self) {
-        let _ =self.run_action();
-        std::process::exit(0);
+        finish(self.run_action());
+    }
+}
+
+/// Reports the result of an action and quits, yappla is done once an item ran.
+pub fn finish(result: Result<(), ActionError>) -> ! {
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
     }
+    std::process::exit(0);
 }
 
 
+#[derive(Debug)]
 pub struct ActionError {
     pub(crate)cause: std::io::Error,
     pub(crate)error: String,
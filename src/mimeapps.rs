@@ -0,0 +1,263 @@
+use std::{
+    collections::HashSet,
+    env,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use relm4::gtk::gio;
+
+use crate::exec::Target;
+
+/// What the `mimeapps.list` files say about one MIME type, as desktop-file
+/// IDs.
+#[derive(Default)]
+pub struct Associations {
+    /// `[Default Applications]`, from the most important file down. The first
+    /// of them that is installed is the default.
+    pub defaults: Vec<String>,
+    /// `[Added Associations]` that no more important file removed.
+    pub added: Vec<String>,
+    /// `[Removed Associations]`, which also hide apps listing the type in
+    /// their `MimeType` key.
+    pub removed: HashSet<String>,
+}
+
+/// Guesses the MIME type of `target` from its name and its first bytes,
+/// using the shared-mime-info globs and magic through GIO. URLs get the
+/// `x-scheme-handler/<scheme>` type their handlers register.
+pub fn guess_type(target: &Target) -> String {
+    let path = match target.as_path() {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let uri = target.as_uri();
+            let scheme = uri.split(':').next().unwrap_or_default();
+            return format!("x-scheme-handler/{}", scheme.to_lowercase());
+        }
+    };
+
+    if path.is_dir() {
+        return "inode/directory".to_string();
+    }
+
+    let data = read_head(&path);
+    let (content_type, _) = gio::content_type_guess(Some(&path), data.as_deref());
+
+    gio::content_type_get_mime_type(&content_type)
+        .map(|it| it.to_string())
+        .unwrap_or_else(|| content_type.to_string())
+}
+
+/// Whether a `MimeType` entry of an app covers `mime`, directly or as a
+/// parent type, like `text/plain` for `text/x-python`.
+pub fn handles(mime: &str, app_type: &str) -> bool {
+    mime == app_type || gio::content_type_is_a(mime, app_type)
+}
+
+/// Collects the associations of `mime` from `files`, the `mimeapps.list`
+/// files from the most important down, as `list_files` returns them.
+pub fn associations(mime: &str, files: &[PathBuf]) -> Associations {
+    let mut associations = Associations::default();
+
+    for path in files {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+
+        let mut removed = Vec::new();
+
+        for (group, key, ids) in parse(&content) {
+            if key != mime {
+                continue;
+            }
+
+            match group {
+                "Default Applications" => associations.defaults.extend(ids),
+                "Added Associations" => associations.added.extend(
+                    ids.into_iter()
+                        .filter(|it| !associations.removed.contains(it)),
+                ),
+                "Removed Associations" => removed.extend(ids),
+                _ => {}
+            }
+        }
+
+        // A removal only affects less important files.
+        associations.removed.extend(removed);
+    }
+
+    associations
+}
+
+/// Yields `(group, key, ids)` for every key of `content`.
+fn parse(content: &str) -> impl Iterator<Item = (&str, &str, Vec<String>)> {
+    let mut group = "";
+
+    content.lines().filter_map(move |line| {
+        let line = line.trim();
+
+        if let Some(name) = line.strip_prefix('[').and_then(|it| it.strip_suffix(']')) {
+            group = name;
+            return None;
+        }
+
+        if line.starts_with('#') {
+            return None;
+        }
+
+        let (key, value) = line.split_once('=')?;
+        let ids = value
+            .split(';')
+            .map(str::trim)
+            .filter(|it| !it.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Some((group, key.trim(), ids))
+    })
+}
+
+/// `$desktop-mimeapps.list` and `mimeapps.list` in the user's and the
+/// system's config directories, then in the application directories, in the
+/// order of the XDG MIME Applications spec.
+pub fn list_files(desktops: &[String]) -> Vec<PathBuf> {
+    let home = env::home_dir().unwrap_or_else(|| PathBuf::from("/"));
+
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".config"));
+    let config_dirs = env::var_os("XDG_CONFIG_DIRS").unwrap_or_else(|| "/etc/xdg".into());
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".local/share"));
+    let data_dirs =
+        env::var_os("XDG_DATA_DIRS").unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+
+    let mut dirs = vec![config_home];
+    dirs.extend(env::split_paths(&config_dirs));
+    dirs.push(data_home.join("applications"));
+    dirs.extend(env::split_paths(&data_dirs).map(|it| it.join("applications")));
+
+    let names: Vec<String> = desktops
+        .iter()
+        .map(|it| format!("{}-mimeapps.list", it.to_lowercase()))
+        .chain(["mimeapps.list".to_string()])
+        .collect();
+
+    dirs.iter()
+        .flat_map(|dir| names.iter().map(|name| dir.join(name)))
+        .collect()
+}
+
+/// The first few KiB, which is what the magic rules look at.
+fn read_head(path: &Path) -> Option<Vec<u8>> {
+    let mut data = vec![0; 4096];
+    let length = File::open(path).ok()?.read(&mut data).ok()?;
+    data.truncate(length);
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::{associations, parse};
+
+    /// `mimeapps.list` files under the system temp directory, removed when
+    /// dropped.
+    struct TestFiles(PathBuf);
+
+    impl TestFiles {
+        fn new(name: &str, contents: &[&str]) -> (Self, Vec<PathBuf>) {
+            let dir = env::temp_dir().join(format!("yappla-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            let files = contents
+                .iter()
+                .enumerate()
+                .map(|(i, content)| {
+                    let path = dir.join(format!("{}-mimeapps.list", i));
+                    fs::write(&path, content).unwrap();
+                    path
+                })
+                .collect();
+
+            (Self(dir), files)
+        }
+    }
+
+    impl Drop for TestFiles {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn comments_and_blank_values() {
+        let content = "# comment\n[Added Associations]\n  # indented\n\
+                       text/plain = a.desktop;;b.desktop ;\nimage/png=\n\n[Other]\nx=y";
+
+        let ids = |ids: &[&str]| ids.iter().map(|it| it.to_string()).collect::<Vec<_>>();
+        let keys: Vec<_> = parse(content).collect();
+
+        assert_eq!(
+            keys,
+            [
+                ("Added Associations", "text/plain", ids(&["a.desktop", "b.desktop"])),
+                ("Added Associations", "image/png", ids(&[])),
+                ("Other", "x", ids(&["y"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn removals_only_affect_less_important_files() {
+        let (_dir, files) = TestFiles::new(
+            "mimeapps-order",
+            &[
+                "[Added Associations]\ntext/plain=a.desktop;\n",
+                "[Removed Associations]\ntext/plain=a.desktop;b.desktop;\n",
+                "[Added Associations]\ntext/plain=b.desktop;c.desktop;\n",
+            ],
+        );
+
+        let associations = associations("text/plain", &files);
+
+        assert_eq!(associations.added, ["a.desktop", "c.desktop"]);
+        assert!(associations.removed.contains("a.desktop"));
+        assert!(associations.removed.contains("b.desktop"));
+    }
+
+    #[test]
+    fn removal_in_the_same_file() {
+        let (_dir, files) = TestFiles::new(
+            "mimeapps-same-file",
+            &["[Added Associations]\ntext/plain=a.desktop;\n\
+               [Removed Associations]\ntext/plain=a.desktop;b.desktop;\n"],
+        );
+
+        let associations = associations("text/plain", &files);
+
+        assert_eq!(associations.added, ["a.desktop"]);
+        assert!(associations.removed.contains("b.desktop"));
+    }
+
+    #[test]
+    fn defaults_from_the_most_important_file_down() {
+        let (_dir, files) = TestFiles::new(
+            "mimeapps-defaults",
+            &[
+                "[Default Applications]\ntext/plain=missing.desktop;a.desktop\n",
+                "[Default Applications]\ntext/plain=b.desktop\nimage/png=c.desktop\n",
+            ],
+        );
+
+        let associations = associations("text/plain", &files);
+
+        // `open-with` picks the first of them that is installed.
+        assert_eq!(associations.defaults, ["missing.desktop", "a.desktop", "b.desktop"]);
+        assert!(associations.added.is_empty());
+    }
+}
@@ -1,100 +1,543 @@
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::HashSet,
     env::{self, home_dir},
-    fs,
-    path::PathBuf,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
     process::Command,
 };
 
-use freedesktop_file_parser::{EntryType, parse};
+use freedesktop_file_parser::{DesktopEntry, EntryType, LocaleString, ParseError, parse};
 use glib::object::Cast;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 
 use crate::{
-    index_list::IndexList,
-    menu_item_model::{ActionError, MenuItemModel},
+    appimage, cli,
+    config::{Config, LaunchStrategy},
+    exec::{self, ExecContext, Target},
+    frecency::Frecency,
+    index_list::{Index, IndexList},
+    launch::{self, ActivationTokenProvider, DisplayTokenProvider},
+    locale,
+    menu::{self, Menu, MenuApp},
+    menu_item_model::{self, ActionError, MenuItemModel},
     modes::mode::Mode,
-    search::{Searchable, Searcher},
+    search::{self, Searchable, Searcher},
+    terminal::Terminal,
 };
 
+mod cache;
+
+/// Factor for search scores of pinned apps, so they win close calls.
+const PINNED_BOOST: f64 = 1.2;
+
+/// Recently used apps listed above the categories.
+const RECENT_APPS: usize = 8;
+
+use cache::{CachedEntry, IndexCache};
+
 pub struct AppsMode {
-    apps: Vec<Application>,
+    apps: Vec<Entry>,
     indecies_buffer: RefCell<Vec<(u32, f64)>>,
     model: IndexList,
+    frecency: Frecency,
+    /// Desktop-file IDs from the `apps` config, changed with Ctrl+P and Ctrl+H.
+    pinned: RefCell<Vec<String>>,
+    hidden: RefCell<Vec<String>>,
+    /// `--show-hidden` lists hidden apps too, so they can be shown again.
+    show_hidden: bool,
+    /// Files from `--open`, passed to whichever app gets launched.
+    open_targets: Vec<Target>,
+    /// `open_targets` plus the files typed after the app name in the query.
+    targets: RefCell<Vec<Target>>,
+    /// The XDG menu, flattened. The first category is the root, which has no
+    /// name. Empty when `apps.flat` is set.
+    categories: Vec<Category>,
+    /// Category listed when the query is empty.
+    current_category: Cell<usize>,
+    back: Back,
+}
+
+/// A row of the list, which has apps, categories and the `..` entry.
+enum Row<'a> {
+    App(&'a Entry),
+    Category(usize),
+    Back,
 }
 
 impl AppsMode {
     pub fn new() -> Self {
+        let mut apps = Self::load_entries(false);
+
+        // Apps that were never launched are listed alphabetically, instead of
+        // in the order the file system returned them.
+        apps.sort_by_cached_key(|it| it.name().to_lowercase());
+
+        let indecies_buffer = RefCell::new(Vec::with_capacity(apps.len()));
+        let model = IndexList::with_capacity(apps.len());
+        let open_targets: Vec<Target> = cli::values("--open")
+            .iter()
+            .map(|it| Target::parse(it))
+            .collect();
+
+        let mut categories = Vec::new();
+
+        if !Config::get().apps.flat {
+            let menu_apps: Vec<MenuApp> = apps
+                .iter()
+                .map(|it| MenuApp {
+                    id: it.id(),
+                    categories: it.categories(),
+                })
+                .collect();
+
+            let menu = menu::load(&menu_apps, &locale::message_locales());
+            Category::flatten(menu, None, &mut categories);
+        }
+
+        Self {
+            apps,
+            indecies_buffer,
+            model,
+            frecency: Frecency::load("apps"),
+            pinned: RefCell::new(Config::get().apps.pinned.clone()),
+            hidden: RefCell::new(Config::get().apps.hidden.clone()),
+            show_hidden: cli::has_flag("--show-hidden"),
+            targets: RefCell::new(open_targets.clone()),
+            open_targets,
+            categories,
+            current_category: Cell::new(0),
+            back: Back {
+                name: "..".to_string(),
+            },
+        }
+    }
+
+    fn row(&self, index: u32) -> Row<'_> {
+        let index = index as usize;
+
+        if index < self.apps.len() {
+            Row::App(&self.apps[index])
+        } else if index < self.apps.len() + self.categories.len() {
+            Row::Category(index - self.apps.len())
+        } else {
+            Row::Back
+        }
+    }
+
+    /// Lists the current category: the `..` entry or, at the top, pinned and
+    /// recently used apps, then subcategories and apps.
+    fn category_indices(&self) -> Vec<u32> {
+        let current = self.current_category.get();
+        let category = &self.categories[current];
+        let mut indices = Vec::new();
+
+        if current == 0 {
+            let pinned = self.pinned.borrow();
+
+            for id in pinned.iter() {
+                if let Some(i) = self.apps.iter().position(|it| it.id() == id) {
+                    indices.push(i as u32);
+                }
+            }
+
+            let mut recent: Vec<u32> = (0..(self.apps.len() as u32))
+                .filter(|i| self.frecency.score(self.apps[*i as usize].id()) > 0.0)
+                .filter(|i| !indices.contains(i))
+                .collect();
+
+            self.frecency.sort(&mut recent, |i| self.apps[i as usize].id());
+            indices.extend(recent.into_iter().take(RECENT_APPS));
+        } else {
+            indices.push((self.apps.len() + self.categories.len()) as u32);
+        }
+
+        indices.extend(
+            category
+                .categories
+                .iter()
+                .map(|it| (self.apps.len() + it) as u32),
+        );
+        indices.extend(category.apps.iter().map(|it| *it as u32));
+
+        indices.retain(|i| match self.row(*i) {
+            Row::App(entry) => self.is_listed(entry),
+            _ => true,
+        });
+        indices
+    }
+
+    /// Reads the applications and links of every application directory, from
+    /// the cache where it is up to date. `NoDisplay` apps, which are meant to
+    /// only open files and URLs, are left out unless `with_no_display` is set.
+    pub(super) fn load_entries(with_no_display: bool) -> Vec<Entry> {
         let search_paths = Self::get_desktop_search_paths();
+        let locales = locale::message_locales();
+        let desktops = Self::get_current_desktops();
+        let debug = cli::has_flag("--debug");
+
+        let cache = IndexCache::load(&locales, &desktops);
+        let mut updated_cache = IndexCache::new(&locales, &desktops);
 
         let mut apps = Vec::new();
 
-        for dir in &search_paths {
+        let mut seen_ids = HashSet::new();
+
+        for (dir, source) in &search_paths {
             if !dir.is_dir() {
                 continue;
             }
 
-            if let Ok(entries) = fs::read_dir(dir) {
-                for entry in entries.flatten() {
-                    let path = entry.path();
+            for file in cache.files(dir, &mut updated_cache) {
+                // The first directory providing an ID wins, even if its entry
+                // is skipped: `Hidden=true` in a user's copy deletes the
+                // system one.
+                let result = if !seen_ids.insert(file.id.clone()) {
+                    Err(SkipReason::Shadowed(file.id).to_string())
+                } else {
+                    match file.entry {
+                        CachedEntry::Parsed(entry) if entry.no_display() && !with_no_display => {
+                            Err(SkipReason::NoDisplay.to_string())
+                        }
+                        CachedEntry::Parsed(entry) => Self::check_try_exec(*entry),
+                        CachedEntry::Skipped(reason) => Err(reason),
+                    }
+                };
 
-                    if path.extension().and_then(|s| s.to_str()) != Some("desktop") {
-                        continue;
+                match result {
+                    Ok(entry) => apps.push(entry.with_source(*source)),
+                    Err(reason) if debug => {
+                        eprintln!("Skipped {}: {}", file.path.display(), reason)
                     }
+                    Err(_) => {}
+                }
+            }
+        }
 
-                    let content = match fs::read_to_string(&path) {
-                        Ok(c) => c,
-                        Err(_) => continue,
-                    };
+        if !updated_cache.same_as(&cache)
+            && let Err(err) = updated_cache.save()
+        {
+            eprintln!("Failed to write the desktop entry cache: {}", err);
+        }
 
-                    let desktop = match parse(&content) {
-                        Ok(d) => d,
-                        Err(_) => continue,
-                    };
+        apps
+    }
 
-                    let desktop_entry = match &desktop.entry.entry_type {
-                        EntryType::Application(app) => app,
-                        _ => continue,
-                    };
+    /// Lists `.desktop` files under `dir` with their desktop-file IDs. Files in
+    /// subdirectories get the path relative to `dir` with `/` replaced by `-`,
+    /// so `kde/konsole.desktop` becomes `kde-konsole.desktop`. Every directory
+    /// walked through is added to `dirs`. Symlinked directories are followed,
+    /// but each directory is walked once, so a symlink loop ends the walk.
+    fn get_desktop_files(dir: &Path, dirs: &mut Vec<PathBuf>) -> Vec<(String, PathBuf)> {
+        let mut files = Vec::new();
+        let mut pending = vec![(dir.to_path_buf(), String::new())];
+        let mut visited = HashSet::new();
 
-                    if desktop.entry.no_display.unwrap_or(false) {
-                        continue;
-                    }
+        while let Some((dir, prefix)) = pending.pop() {
+            if !fs::canonicalize(&dir).is_ok_and(|it| visited.insert(it)) {
+                continue;
+            }
 
-                    let name = desktop.entry.name.default;
-                    let exec = desktop_entry.exec.clone().unwrap_or("".to_string());
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
 
-                    let mut keywords = desktop_entry.keywords.clone().unwrap_or_default().default;
+            dirs.push(dir);
 
-                    keywords.iter_mut().for_each(|it| *it = it.to_lowercase());
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let file_name = entry.file_name().to_string_lossy().to_string();
 
-                    let lower_name = name.to_lowercase();
+                if path.is_dir() {
+                    pending.push((path, format!("{}{}-", prefix, file_name)));
+                } else if path.extension().and_then(|s| s.to_str()) == Some("desktop") {
+                    files.push((format!("{}{}", prefix, file_name), path));
+                }
+            }
+        }
 
-                    if exec.is_empty() {
-                        continue;
-                    }
+        files
+    }
 
-                    apps.push(Application {
-                        display_name: name.to_string(),
-                        lower_name: lower_name,
-                        keywords: keywords,
-                        exec: exec.to_string(),
-                    });
-                }
+    fn load_entry(
+        id: &str,
+        path: &Path,
+        locales: &[String],
+        desktops: &[String],
+    ) -> Result<Entry, SkipReason> {
+        let content = fs::read_to_string(path).map_err(SkipReason::Unreadable)?;
+        let desktop = parse(&content).map_err(SkipReason::Invalid)?;
+
+        if desktop.entry.hidden.unwrap_or(false) {
+            return Err(SkipReason::Hidden);
+        }
+
+        Self::check_show_in(&desktop.entry, desktops)?;
+
+        let no_display = desktop.entry.no_display.unwrap_or(false);
+
+        let desktop_entry = match &desktop.entry.entry_type {
+            EntryType::Application(app) => app,
+            EntryType::Link(_) if no_display => return Err(SkipReason::NoDisplay),
+            EntryType::Link(link) => {
+                return Self::load_link(id, &desktop.entry, &link.url, locales).map(Entry::Link);
             }
+            other => return Err(SkipReason::NotApplication(other.to_string())),
+        };
+
+        let name = locale::localized(&desktop.entry.name, locales);
+        let exec = desktop_entry.exec.clone().unwrap_or("".to_string());
+        let lower_names = Self::lower_names(&desktop.entry.name, locales);
+
+        let mut keywords = Vec::new();
+
+        if let Some(list) = &desktop_entry.keywords {
+            keywords.extend_from_slice(locale::localized_list(list, locales));
+            keywords.extend_from_slice(&list.default);
         }
 
-        let indecies_buffer = RefCell::new(Vec::with_capacity(apps.len()));
-        let model = IndexList::with_capacity(apps.len());
+        keywords.iter_mut().for_each(|it| *it = it.to_lowercase());
+        keywords.sort();
+        keywords.dedup();
 
-        Self {
-            apps,
-            indecies_buffer,
-            model,
+        let generic_name = desktop
+            .entry
+            .generic_name
+            .as_ref()
+            .map(|it| locale::localized(it, locales).to_string());
+        let comment = desktop
+            .entry
+            .comment
+            .as_ref()
+            .map(|it| locale::localized(it, locales).to_string());
+
+        let mut generic_names = Vec::new();
+
+        if let Some(generic) = &desktop.entry.generic_name {
+            generic_names.push(locale::localized(generic, locales).to_lowercase());
+            generic_names.push(generic.default.to_lowercase());
+            generic_names.dedup();
+        }
+
+        let mut comment_words = Vec::new();
+
+        if let Some(comment) = &desktop.entry.comment {
+            let localized = locale::localized(comment, locales);
+
+            for text in [localized, comment.default.as_str()] {
+                comment_words.extend(
+                    text.split(|it: char| !it.is_alphanumeric())
+                        .filter(|it| it.len() > 2)
+                        .map(str::to_lowercase),
+                );
+            }
+
+            comment_words.sort();
+            comment_words.dedup();
         }
+
+        let categories = desktop_entry
+            .categories
+            .iter()
+            .flatten()
+            .map(|it| split_camel_case(it))
+            .collect();
+        let menu_categories = desktop_entry.categories.clone().unwrap_or_default();
+
+        // What power users type by habit: the binary, the window class and
+        // the ID, like `nautilus` or `org.gnome.Nautilus`.
+        let mut other_names: Vec<String> = [
+            exec::program_name(&exec),
+            desktop_entry.startup_wm_class.clone(),
+            Some(id.strip_suffix(".desktop").unwrap_or(id).to_string()),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|it| it.to_lowercase())
+        .filter(|it| !lower_names.contains(it))
+        .collect();
+
+        other_names.sort();
+        other_names.dedup();
+
+        let dbus_activatable = desktop.entry.dbus_activatable.unwrap_or(false);
+
+        if exec.is_empty() && !dbus_activatable {
+            return Err(SkipReason::NoExec);
+        }
+
+        Ok(Application {
+            id: id.to_string(),
+            terminal: desktop_entry.terminal.unwrap_or(false),
+            dbus_activatable,
+            no_display,
+            prefers_non_default_gpu: desktop_entry.prefers_non_default_gpu.unwrap_or(false),
+            working_dir: desktop_entry
+                .path
+                .as_ref()
+                .filter(|it| !it.is_empty())
+                .map(PathBuf::from),
+            display_name: name.to_string(),
+            lower_names,
+            keywords,
+            generic_names,
+            comment_words,
+            categories,
+            menu_categories,
+            other_names,
+            description: comment.or(generic_name),
+            exec,
+            icon: desktop.entry.icon.map(|it| it.content),
+            desktop_file: path.to_path_buf(),
+            try_exec: desktop_entry.try_exec.clone(),
+            mime_types: desktop_entry.mime_type.clone().unwrap_or_default(),
+            source: Source::default(),
+        }
+        .into())
     }
 
-    fn get_desktop_search_paths() -> HashSet<PathBuf> {
+    fn load_link(
+        id: &str,
+        entry: &DesktopEntry,
+        url: &str,
+        locales: &[String],
+    ) -> Result<Link, SkipReason> {
+        if url.is_empty() {
+            return Err(SkipReason::NoUrl);
+        }
+
+        let comment = entry
+            .comment
+            .as_ref()
+            .map(|it| locale::localized(it, locales).to_string());
+
+        Ok(Link {
+            id: id.to_string(),
+            display_name: locale::localized(&entry.name, locales).to_string(),
+            lower_names: Self::lower_names(&entry.name, locales),
+            description: comment.unwrap_or_else(|| url.to_string()),
+            url: url.to_string(),
+            icon: entry.icon.as_ref().map(|it| it.content.clone()),
+            source: Source::default(),
+        })
+    }
+
+    /// Both the translated and the untranslated name are searchable, so
+    /// "firefox" still finds "Mozilla Firefox" in a localized session.
+    fn lower_names(name: &LocaleString, locales: &[String]) -> Vec<String> {
+        let mut lower_names = vec![locale::localized(name, locales).to_lowercase()];
+        let default_name = name.default.to_lowercase();
+
+        if !lower_names.contains(&default_name) {
+            lower_names.push(default_name);
+        }
+
+        lower_names
+    }
+
+    /// Checked on every start rather than when parsing, since installing the
+    /// program doesn't touch the cached directory.
+    fn check_try_exec(entry: Entry) -> Result<Entry, String> {
+        if let Entry::Application(app) = &entry
+            && let Some(program) = &app.try_exec
+            && !exec::is_executable_in_path(program)
+        {
+            return Err(SkipReason::TryExec(program.clone()).to_string());
+        }
+
+        Ok(entry)
+    }
+
+    /// In `gimp ~/pic.png` only `gimp` is searched for, the rest is opened
+    /// with the app.
+    fn split_targets(query: &str) -> (String, Vec<Target>) {
+        let mut words = Vec::new();
+        let mut targets = Vec::new();
+
+        for (i, word) in query.split_whitespace().enumerate() {
+            if i > 0 && Target::looks_like_target(word) {
+                targets.push(Target::parse(word));
+            } else {
+                words.push(word);
+            }
+        }
+
+        (words.join(" "), targets)
+    }
+
+    fn is_listed(&self, entry: &Entry) -> bool {
+        self.show_hidden || !self.hidden.borrow().contains(&entry.id().to_string())
+    }
+
+    /// Adds `id` to or removes it from `list` and saves the list as the `key`
+    /// of the `apps` config.
+    fn toggle(list: &RefCell<Vec<String>>, key: &str, id: &str) {
+        let mut list = list.borrow_mut();
+
+        match list.iter().position(|it| it == id) {
+            Some(i) => {
+                list.remove(i);
+            }
+            None => list.push(id.to_string()),
+        }
+
+        let result = Config::update(|config| {
+            let apps = config.entry("apps").or_insert_with(|| json!({}));
+
+            if !apps.is_object() {
+                *apps = json!({});
+            }
+
+            apps[key] = json!(*list);
+        });
+
+        if let Err(err) = result {
+            eprintln!("Failed to save the config: {}", err);
+        }
+    }
+
+    /// `XDG_CURRENT_DESKTOP` is a colon separated list, like `ubuntu:GNOME`.
+    pub(super) fn get_current_desktops() -> Vec<String> {
+        env::var("XDG_CURRENT_DESKTOP")
+            .unwrap_or_default()
+            .split(':')
+            .filter(|it| !it.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Applies `OnlyShowIn` and `NotShowIn`. Current desktops are checked in
+    /// order and the first one listed in either key decides. If none of them
+    /// is listed, the entry is shown unless it has an `OnlyShowIn` key.
+    fn check_show_in(entry: &DesktopEntry, desktops: &[String]) -> Result<(), SkipReason> {
+        let only_show_in = entry.only_show_in.as_deref().unwrap_or_default();
+        let not_show_in = entry.not_show_in.as_deref().unwrap_or_default();
+
+        for desktop in desktops {
+            if only_show_in.contains(desktop) {
+                return Ok(());
+            }
+
+            if not_show_in.contains(desktop) {
+                return Err(SkipReason::NotShowIn(desktop.clone()));
+            }
+        }
+
+        if entry.only_show_in.is_some() {
+            return Err(SkipReason::OnlyShowIn(only_show_in.to_vec()));
+        }
+
+        Ok(())
+    }
+
+    /// Returns application directories from the most to the least important
+    /// one, as the XDG Base Directory spec orders them, followed by the extra
+    /// sources from the config.
+    fn get_desktop_search_paths() -> Vec<(PathBuf, Source)> {
+        let sources = &Config::get().sources;
         let mut paths = Vec::new();
 
         let user_data = env::var_os("XDG_DATA_HOME")
@@ -105,40 +548,111 @@ impl AppsMode {
                     .join(".local/share")
             });
 
-        paths.push(user_data.join("applications"));
+        paths.push((user_data.join("applications"), Source::Xdg));
 
         let system_dirs =
             env::var_os("XDG_DATA_DIRS").unwrap_or_else(|| "/usr/local/share:/usr/share".into());
 
         for dir in env::split_paths(&system_dirs) {
-            paths.push(dir.join("applications"));
+            paths.push((dir.join("applications"), Source::Xdg));
+        }
+
+        if sources.flatpak {
+            if let Some(home) = home_dir() {
+                paths.push((
+                    home.join(".local/share/flatpak/exports/share/applications"),
+                    Source::Flatpak,
+                ));
+            }
+            paths.push((
+                PathBuf::from("/var/lib/flatpak/exports/share/applications"),
+                Source::Flatpak,
+            ));
+        }
+
+        if sources.snap {
+            paths.push((
+                PathBuf::from("/var/lib/snapd/desktop/applications"),
+                Source::Snap,
+            ));
+        }
+
+        if sources.nix {
+            if let Some(home) = home_dir() {
+                paths.push((home.join(".nix-profile/share/applications"), Source::Nix));
+            }
+
+            if let Ok(user) = env::var("USER") {
+                paths.push((
+                    PathBuf::from(format!("/etc/profiles/per-user/{}/share/applications", user)),
+                    Source::Nix,
+                ));
+            }
+        }
+
+        if !sources.appimages.is_empty() {
+            let dirs: Vec<PathBuf> = sources
+                .appimages
+                .iter()
+                .map(|it| Config::expand_home(it))
+                .collect();
+
+            paths.push((appimage::sync(&dirs), Source::AppImage));
         }
 
-        paths.push(PathBuf::from("/var/lib/flatpak/exports/share/applications"));
-        if let Some(home) = home_dir() {
-            paths.push(home.join(".local/share/flatpak/exports/share/applications"));
+        for dir in &sources.directories {
+            paths.push((Config::expand_home(dir), Source::Custom));
         }
 
-        paths.into_iter().collect()
+        let mut seen = HashSet::new();
+        paths.retain(|it| seen.insert(it.0.clone()));
+        paths
     }
 }
 
 impl Mode for AppsMode {
     fn search(&self, query: String) -> gtk::gio::ListModel {
+        let (query, query_targets) = Self::split_targets(&query);
+        let mut targets = self.targets.borrow_mut();
+        targets.clone_from(&self.open_targets);
+        targets.extend(query_targets);
+
         if query.is_empty() {
             return self.filled_model();
         }
 
         let query_lower = query.to_lowercase();
         let searcher = Searcher::new(&self.apps);
-        let entries = searcher.search(query_lower.as_str());
+        let entries = searcher.search(query_lower.as_str()).map(|(i, score)| {
+            (i, score * self.frecency.boost(self.apps[i as usize].id()))
+        });
 
+        let pinned = self.pinned.borrow();
         let mut indecies_buffer = self.indecies_buffer.borrow_mut();
 
         indecies_buffer.clear();
-        indecies_buffer.extend(entries);
+        indecies_buffer.extend(entries.filter_map(|(i, score)| {
+            let app = &self.apps[i as usize];
+
+            if !self.is_listed(app) {
+                None
+            } else if pinned.iter().any(|it| it == app.id()) {
+                Some((i, score * PINNED_BOOST))
+            } else {
+                Some((i, score))
+            }
+        }));
         indecies_buffer.sort_by(|a, b| b.1.total_cmp(&a.1));
 
+        // An alias puts its app on top, even when the app is hidden or its
+        // name has nothing in common with the alias.
+        let alias = Config::get().apps.aliases.get(&query_lower);
+
+        if let Some(i) = alias.and_then(|id| self.apps.iter().position(|it| it.id() == id)) {
+            indecies_buffer.retain(|it| it.0 != i as u32);
+            indecies_buffer.insert(0, (i as u32, f64::INFINITY));
+        }
+
         self.model
             .set_indecies(indecies_buffer.iter().map(|it| it.0));
 
@@ -146,65 +660,713 @@ impl Mode for AppsMode {
     }
 
     fn filled_model(&self) -> gtk::gio::ListModel {
-        self.model
-            .set_indecies((0..(self.apps.len())).map(|i| i as u32));
+        if !self.categories.is_empty() {
+            self.model.set_indecies(self.category_indices());
+            return self.model();
+        }
+
+        let pinned = self.pinned.borrow();
+        let mut indices: Vec<u32> = (0..(self.apps.len()))
+            .filter(|i| self.is_listed(&self.apps[*i]))
+            .map(|i| i as u32)
+            .collect();
+        self.frecency
+            .sort(&mut indices, |i| self.apps[i as usize].id());
+
+        // Pinned apps come first, in the order they were pinned.
+        indices.sort_by_key(|i| {
+            let id = self.apps[*i as usize].id();
+            pinned.iter().position(|it| it == id).unwrap_or(usize::MAX)
+        });
+
+        self.model.set_indecies(indices);
         self.model()
     }
 
     fn get_menu_item_model<'a>(&'a self, item: &crate::index_list::Index) -> &'a dyn MenuItemModel {
-        &self.apps[item.index() as usize]
+        match self.row(item.index()) {
+            Row::App(entry) => entry,
+            Row::Category(i) => &self.categories[i],
+            Row::Back => &self.back,
+        }
     }
 
     fn model(&self) -> gtk::gio::ListModel {
         self.model.clone().upcast()
     }
+
+    fn activate(&self, item: &Index) -> bool {
+        let entry = match self.row(item.index()) {
+            Row::App(entry) => entry,
+            Row::Category(i) => {
+                self.current_category.set(i);
+                return true;
+            }
+            Row::Back => {
+                let current = &self.categories[self.current_category.get()];
+                self.current_category.set(current.parent.unwrap_or(0));
+                return true;
+            }
+        };
+
+        let result = match entry {
+            Entry::Application(app) => app.launch(&self.targets.borrow()),
+            Entry::Link(link) => link.run_action(),
+        };
+
+        // A launch that failed shouldn't rank the app higher.
+        if result.is_ok() {
+            self.frecency.record(entry.id());
+        }
+
+        menu_item_model::finish(result)
+    }
+
+    fn highlight(&self, item: &Index, query: &str) -> Option<Vec<usize>> {
+        let (query, _) = Self::split_targets(query);
+        self.get_menu_item_model(item).highlight(&query.to_lowercase())
+    }
+
+    fn toggle_pinned(&self, item: &Index) -> bool {
+        match self.row(item.index()) {
+            Row::App(entry) => {
+                Self::toggle(&self.pinned, "pinned", entry.id());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn toggle_hidden(&self, item: &Index) -> bool {
+        match self.row(item.index()) {
+            Row::App(entry) => {
+                Self::toggle(&self.hidden, "hidden", entry.id());
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
-struct Application {
-    display_name: String,
-    lower_name: String,
-    keywords: Vec<String>,
-    exec: String,
+/// Why a desktop file didn't make it into the list, printed with `--debug`.
+enum SkipReason {
+    Unreadable(std::io::Error),
+    Invalid(ParseError),
+    NotApplication(String),
+    Hidden,
+    NoDisplay,
+    OnlyShowIn(Vec<String>),
+    NotShowIn(String),
+    TryExec(String),
+    NoExec,
+    NoUrl,
+    Shadowed(String),
 }
 
-impl Searchable for Application {
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Unreadable(err) => write!(f, "failed to read file: {}", err),
+            SkipReason::Invalid(err) => write!(f, "{}", err),
+            SkipReason::NotApplication(kind) => {
+                write!(f, "Type={} is not an application or link", kind)
+            }
+            SkipReason::Hidden => write!(f, "Hidden=true"),
+            SkipReason::NoDisplay => write!(f, "NoDisplay=true"),
+            SkipReason::OnlyShowIn(desktops) => write!(
+                f,
+                "OnlyShowIn={} doesn't match XDG_CURRENT_DESKTOP",
+                desktops.join(";")
+            ),
+            SkipReason::NotShowIn(desktop) => write!(f, "NotShowIn contains {}", desktop),
+            SkipReason::TryExec(program) => write!(f, "TryExec={} is not installed", program),
+            SkipReason::NoExec => write!(f, "Exec is empty"),
+            SkipReason::NoUrl => write!(f, "URL is empty"),
+            SkipReason::Shadowed(id) => {
+                write!(f, "{} is already provided by a higher priority directory", id)
+            }
+        }
+    }
+}
+
+/// What a desktop file in an application directory stands for.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) enum Entry {
+    Application(Box<Application>),
+    Link(Link),
+}
+
+impl From<Application> for Entry {
+    fn from(app: Application) -> Self {
+        Entry::Application(Box::new(app))
+    }
+}
+
+impl Entry {
+    /// Desktop-file ID, like `org.gnome.Nautilus.desktop`.
+    pub(super) fn id(&self) -> &str {
+        match self {
+            Entry::Application(app) => &app.id,
+            Entry::Link(link) => &link.id,
+        }
+    }
+
+    fn no_display(&self) -> bool {
+        match self {
+            Entry::Application(app) => app.no_display,
+            Entry::Link(_) => false,
+        }
+    }
+
+    fn categories(&self) -> &[String] {
+        match self {
+            Entry::Application(app) => &app.menu_categories,
+            Entry::Link(_) => &[],
+        }
+    }
+
+    fn with_source(mut self, source: Source) -> Self {
+        match &mut self {
+            Entry::Application(app) => app.source = source,
+            Entry::Link(link) => link.source = source,
+        }
+
+        self
+    }
+
+    fn item(&self) -> &dyn MenuItemModel {
+        match self {
+            Entry::Application(app) => app.as_ref(),
+            Entry::Link(link) => link,
+        }
+    }
+}
+
+impl Searchable for Entry {
     fn score(&self, request: &str) -> f64 {
-        let name_score = self.lower_name.as_str().score(request);
+        match self {
+            Entry::Application(app) => app.score(request),
+            Entry::Link(link) => link.score(request),
+        }
+    }
+}
 
-        self.keywords
+impl MenuItemModel for Entry {
+    fn name(&self) -> &String {
+        self.item().name()
+    }
+
+    fn icon(&self) -> Option<&str> {
+        self.item().icon()
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.item().description()
+    }
+
+    fn tag(&self) -> Option<&str> {
+        self.item().tag()
+    }
+
+    fn highlight(&self, query: &str) -> Option<Vec<usize>> {
+        self.item().highlight(query)
+    }
+
+    fn run_action(&self) -> Result<(), ActionError> {
+        self.item().run_action()
+    }
+}
+
+/// A `Type=Link` entry, a shortcut to a URL like the ones browsers create
+/// for web pages.
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct Link {
+    id: String,
+    display_name: String,
+    lower_names: Vec<String>,
+    /// Translated `Comment`, or the URL when there is no comment.
+    description: String,
+    url: String,
+    icon: Option<String>,
+    #[serde(skip)]
+    source: Source,
+}
+
+impl Searchable for Link {
+    fn score(&self, request: &str) -> f64 {
+        self.lower_names
             .iter()
             .map(|it| it.as_str().score(request))
             .reduce(f64::max)
             .unwrap_or(0.0)
-            .powi(2)
-            .max(name_score)
             .powf(1.3)
     }
 }
 
+impl MenuItemModel for Link {
+    fn name(&self) -> &String {
+        &self.display_name
+    }
+
+    fn icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    fn description(&self) -> Option<&str> {
+        Some(&self.description)
+    }
+
+    /// Links are marked as such, wherever they come from.
+    fn tag(&self) -> Option<&str> {
+        Some("link")
+    }
+
+    /// Opens the URL with the default handler of its scheme.
+    fn run_action(&self) -> Result<(), ActionError> {
+        let mut command = Command::new("xdg-open");
+        command.arg(&self.url);
+
+        launch::spawn_detached(&mut command).map_err(|err| ActionError {
+            command: format!("xdg-open {}", self.url),
+            error: format!("Failed to open {}", self.display_name),
+            cause: err,
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct Application {
+    /// Desktop-file ID, like `org.gnome.Nautilus.desktop`.
+    pub(super) id: String,
+    display_name: String,
+    lower_names: Vec<String>,
+    keywords: Vec<String>,
+    generic_names: Vec<String>,
+    comment_words: Vec<String>,
+    categories: Vec<String>,
+    /// `Categories` as they are, for the menu.
+    menu_categories: Vec<String>,
+    /// Exec program, `StartupWMClass` and desktop-file ID.
+    other_names: Vec<String>,
+    /// Translated `Comment`, or `GenericName` when there is no comment.
+    description: Option<String>,
+    exec: String,
+    icon: Option<String>,
+    desktop_file: PathBuf,
+    terminal: bool,
+    dbus_activatable: bool,
+    /// `NoDisplay`, only listed by `open-with`.
+    no_display: bool,
+    /// `PrefersNonDefaultGPU`, started with `gpu_offload_env`.
+    prefers_non_default_gpu: bool,
+    working_dir: Option<PathBuf>,
+    try_exec: Option<String>,
+    /// `MimeType`, the types the app can open.
+    pub(super) mime_types: Vec<String>,
+    /// Set from the directory an entry was found in, not cached.
+    #[serde(skip)]
+    source: Source,
+}
+
+/// Where an app was found. Apps from the XDG data directories are the usual
+/// case and don't get a tag.
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+enum Source {
+    #[default]
+    Xdg,
+    Flatpak,
+    Snap,
+    Nix,
+    AppImage,
+    Custom,
+}
+
+impl Source {
+    fn tag(self) -> Option<&'static str> {
+        match self {
+            Source::Xdg => None,
+            Source::Flatpak => Some("flatpak"),
+            Source::Snap => Some("snap"),
+            Source::Nix => Some("nix"),
+            Source::AppImage => Some("AppImage"),
+            Source::Custom => Some("custom"),
+        }
+    }
+}
+
+impl Application {
+    /// Scores of the names and of the best other field.
+    fn field_scores(&self, request: &str) -> (f64, f64) {
+        let best = |values: &[String]| {
+            values
+                .iter()
+                .map(|it| it.as_str().score(request))
+                .reduce(f64::max)
+                .unwrap_or(0.0)
+        };
+
+        // What an app is, rather than what it's called, only decides the
+        // order when nothing matches the name better.
+        let other = best(&self.keywords)
+            .powi(2)
+            .max(best(&self.generic_names).powi(2) * 0.9)
+            .max(best(&self.other_names).powi(2) * 0.8)
+            .max(best(&self.categories).powi(2) * 0.7)
+            .max(best(&self.comment_words).powi(2) * 0.6);
+
+        (best(&self.lower_names), other)
+    }
+}
+
+impl Searchable for Application {
+    fn score(&self, request: &str) -> f64 {
+        let (name, other) = self.field_scores(request);
+        name.max(other).powf(1.3)
+    }
+}
+
+/// A submenu of the XDG menu, opened from the list when the query is empty.
+struct Category {
+    name: String,
+    icon: Option<String>,
+    description: Option<String>,
+    parent: Option<usize>,
+    /// Indices into `AppsMode::categories`, sorted by name.
+    categories: Vec<usize>,
+    /// Indices into `AppsMode::apps`.
+    apps: Vec<usize>,
+}
+
+impl Category {
+    /// Appends `menu` and its submenus to `categories` and returns its index.
+    fn flatten(menu: Menu, parent: Option<usize>, categories: &mut Vec<Category>) -> usize {
+        let index = categories.len();
+        let mut apps = menu.apps;
+
+        // `AppsMode::apps` is sorted by name, so sorted indices are too.
+        apps.sort();
+
+        categories.push(Category {
+            name: menu.name,
+            icon: menu.icon,
+            description: menu.comment,
+            parent,
+            categories: Vec::new(),
+            apps,
+        });
+
+        let mut menus = menu.menus;
+        menus.sort_by_cached_key(|it| it.name.to_lowercase());
+
+        for child in menus {
+            let child = Self::flatten(child, Some(index), categories);
+            categories[index].categories.push(child);
+        }
+
+        index
+    }
+}
+
+impl MenuItemModel for Category {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn icon(&self) -> Option<&str> {
+        Some(self.icon.as_deref().unwrap_or("folder"))
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    fn tag(&self) -> Option<&str> {
+        Some("›")
+    }
+
+    /// Opening a category is done by `AppsMode::activate`.
+    fn run_action(&self) -> Result<(), ActionError> {
+        Ok(())
+    }
+}
+
+/// The `..` entry leading back to the parent category.
+struct Back {
+    name: String,
+}
+
+impl MenuItemModel for Back {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn icon(&self) -> Option<&str> {
+        Some("go-up")
+    }
+
+    /// Going back is done by `AppsMode::activate`.
+    fn run_action(&self) -> Result<(), ActionError> {
+        Ok(())
+    }
+}
+
+/// Turns a category like `TextEditor` into `text editor`, which is how people
+/// type it.
+fn split_camel_case(category: &str) -> String {
+    let mut result = String::with_capacity(category.len() + 4);
+    let mut previous_lowercase = false;
+
+    for char in category.chars() {
+        if char.is_uppercase() && previous_lowercase {
+            result.push(' ');
+        }
+        previous_lowercase = char.is_lowercase();
+        result.extend(char.to_lowercase());
+    }
+
+    result
+}
+
 impl MenuItemModel for Application {
     fn name<'a>(&'a self) -> &'a String {
         &self.display_name
     }
 
+    /// Bold chars would be misleading when a keyword or the generic name
+    /// matched rather than the name.
+    fn highlight(&self, query: &str) -> Option<Vec<usize>> {
+        let (name, other) = self.field_scores(query);
+
+        if name < other {
+            return None;
+        }
+
+        search::positions(&self.display_name, query)
+    }
+
+    fn icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    fn tag(&self) -> Option<&str> {
+        self.source.tag()
+    }
+
     fn run_action(&self) -> Result<(), ActionError> {
-        let patterns = [
-            "%f", "%F", "%u", "%U", "%d", "%D", "%n", "%N", "%i", "%c", "%k", "%v", "%m",
-        ];
+        self.launch(&[])
+    }
+}
 
-        let exec = patterns
-            .iter()
-            .fold(self.exec.clone(), |acc, &pattern| acc.replace(pattern, ""));
+impl Application {
+    /// Starts the app with `targets` as its files or URLs.
+    pub(super) fn launch(&self, targets: &[Target]) -> Result<(), ActionError> {
+        self.launch_with(
+            targets,
+            &DisplayTokenProvider,
+            &Config::get().launch,
+            launch::activate_dbus,
+        )
+    }
+
+    /// `launch` with what depends on the session passed in, so that tests can
+    /// replace it.
+    fn launch_with(
+        &self,
+        targets: &[Target],
+        provider: &dyn ActivationTokenProvider,
+        strategy: &LaunchStrategy,
+        activate_dbus: impl FnOnce(
+            &str,
+            &[String],
+            &dyn ActivationTokenProvider,
+            Option<&Path>,
+        ) -> Result<(), glib::Error>,
+    ) -> Result<(), ActionError> {
+        let app_id = self.id.strip_suffix(".desktop").unwrap_or(&self.id);
+        let gpu_env = self.gpu_env();
+
+        // D-Bus activation can't pass the GPU environment, so apps that need
+        // it are started through `Exec` when they have one.
+        if self.dbus_activatable && (gpu_env.is_empty() || self.exec.is_empty()) {
+            let uris: Vec<String> = targets.iter().map(Target::as_uri).collect();
+
+            match activate_dbus(app_id, &uris, provider, Some(&self.desktop_file)) {
+                Ok(()) => return Ok(()),
+                Err(err) => eprintln!(
+                    "D-Bus activation of {} failed, falling back to Exec: {}",
+                    app_id, err
+                ),
+            }
+        }
 
-        Command::new("sh")
-            .arg("-c")
-            .arg(&exec)
-            .spawn()
-            .map_err(|err| ActionError {
+        // Starting in yappla's directory instead would silently break apps
+        // that rely on relative paths.
+        if let Some(dir) = &self.working_dir
+            && !dir.is_dir()
+        {
+            return Err(ActionError {
                 command: self.exec.clone(),
-                error: format!("Failed to launch application"),
-                cause: err,
-            })
-            .map(|_| ())
+                error: format!("Working directory {} doesn't exist", dir.display()),
+                cause: io::Error::new(io::ErrorKind::NotFound, "Path is not a directory"),
+            });
+        }
+
+        let context = ExecContext {
+            name: &self.display_name,
+            icon: self.icon.as_deref(),
+            desktop_file: Some(&self.desktop_file),
+            targets,
+        };
+
+        let argvs = exec::expand(&self.exec, &context).map_err(|err| ActionError {
+            command: self.exec.clone(),
+            error: "Failed to parse Exec".to_string(),
+            cause: io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+        })?;
+
+        for argv in argvs {
+            self.spawn(app_id, argv, &gpu_env, provider, strategy)?;
+        }
+
+        Ok(())
+    }
+
+    /// The offload environment for `PrefersNonDefaultGPU`, if there is a
+    /// second GPU to prefer.
+    fn gpu_env(&self) -> Vec<(String, String)> {
+        if !self.prefers_non_default_gpu {
+            return Vec::new();
+        }
+
+        launch::gpu_offload_env(&Config::get().gpu_offload, launch::render_nodes())
+    }
+
+    fn spawn(
+        &self,
+        app_id: &str,
+        argv: Vec<String>,
+        gpu_env: &[(String, String)],
+        provider: &dyn ActivationTokenProvider,
+        strategy: &LaunchStrategy,
+    ) -> Result<(), ActionError> {
+        let argv = if self.terminal {
+            let terminal = Terminal::detect().ok_or_else(|| ActionError {
+                command: self.exec.clone(),
+                error: "Failed to find a terminal emulator".to_string(),
+                cause: io::Error::new(io::ErrorKind::NotFound, "no terminal configured"),
+            })?;
+
+            terminal.wrap(&argv)
+        } else {
+            argv
+        };
+
+        let argv = launch::apply_strategy(strategy, app_id, argv);
+
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        command.envs(launch::activation_env(provider, Some(&self.desktop_file)));
+
+        command.envs(gpu_env.iter().cloned());
+
+        if let Some(dir) = &self.working_dir {
+            command.current_dir(dir);
+        }
+
+        launch::spawn_detached(&mut command).map_err(|err| ActionError {
+            command: self.exec.clone(),
+            error: "Failed to launch application".to_string(),
+            cause: err,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use relm4::gtk::gio;
+
+    use super::{Application, AppsMode, Entry};
+    use crate::{config::LaunchStrategy, exec::Target, launch::ActivationTokenProvider};
+
+    struct NoTokens;
+
+    impl ActivationTokenProvider for NoTokens {
+        fn token(&self, _: Option<&Path>) -> Option<String> {
+            None
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("yappla-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        path
+    }
+
+    /// A D-Bus activatable app in `dir` whose `Exec` writes to `dir/out`.
+    fn dbus_app(dir: &Path) -> Application {
+        let path = dir.join("org.example.Fake.desktop");
+        let content = format!(
+            "[Desktop Entry]\nType=Application\nName=Fake\nDBusActivatable=true\n\
+             Exec=sh -c \"echo launched > {}\"\n",
+            dir.join("out").display()
+        );
+        fs::write(&path, content).unwrap();
+
+        match AppsMode::load_entry("org.example.Fake.desktop", &path, &[], &[]) {
+            Ok(Entry::Application(app)) => *app,
+            _ => panic!("{} isn't an app", path.display()),
+        }
+    }
+
+    #[test]
+    fn dbus_activation() {
+        let dir = test_dir("dbus-activation");
+        let app = dbus_app(&dir);
+        let targets = [Target::Uri("https://example.org/".to_string())];
+        let mut activated = None;
+
+        app.launch_with(&targets, &NoTokens, &LaunchStrategy::Direct, |app_id, uris, _, _| {
+            activated = Some((app_id.to_string(), uris.to_vec()));
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(
+            activated,
+            Some(("org.example.Fake".to_string(), vec!["https://example.org/".to_string()]))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn exec_fallback() {
+        let dir = test_dir("exec-fallback");
+        let app = dbus_app(&dir);
+
+        app.launch_with(&[], &NoTokens, &LaunchStrategy::Direct, |_, _, _, _| {
+            Err(glib::Error::new(gio::IOErrorEnum::Failed, "no session bus"))
+        })
+        .unwrap();
+
+        let start = Instant::now();
+
+        while fs::read_to_string(dir.join("out")).unwrap_or_default() != "launched\n" {
+            assert!(start.elapsed() < Duration::from_secs(10), "Exec didn't run");
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
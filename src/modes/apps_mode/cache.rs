@@ -0,0 +1,466 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{AppsMode, Entry};
+use crate::config::Config;
+
+/// Bumped whenever `Entry` or the cache layout changes, so that an
+/// older cache gets rebuilt instead of failing to deserialize.
+const VERSION: u32 = 10;
+
+/// How far apart a directory change and a scan have to be for the scan to
+/// be trusted. Some file systems store mtimes in whole seconds, so a file
+/// added in the same second as the scan could leave the mtime unchanged.
+const MTIME_GRANULARITY: Duration = Duration::from_secs(1);
+
+/// Parsed desktop entries of every application directory, kept in
+/// `$XDG_CACHE_HOME/yappla/apps.json`. A directory is listed again when the
+/// mtime of it or one of its subdirectories changed, which happens whenever a
+/// `.desktop` file is added, removed or replaced by a package manager, unless
+/// that happened too close to the scan to tell. A file
+/// is parsed again when its mtime or size changed, which also catches files
+/// edited in place.
+///
+/// Entries are cached before `TryExec` and shadowing are applied, those
+/// depend on `$PATH` and on the other directories.
+#[derive(Serialize, Deserialize)]
+pub(super) struct IndexCache {
+    version: u32,
+    /// Translations and `OnlyShowIn` depend on these, a cache built for other
+    /// values is thrown away as a whole.
+    locales: Vec<String>,
+    desktops: Vec<String>,
+    dirs: Vec<CachedDir>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedDir {
+    path: PathBuf,
+    /// The directory and all its subdirectories.
+    mtimes: Vec<(PathBuf, SystemTime)>,
+    /// When the directory was listed.
+    scanned: SystemTime,
+    files: Vec<CachedFile>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) struct CachedFile {
+    pub id: String,
+    pub path: PathBuf,
+    pub entry: CachedEntry,
+    stamp: Option<Stamp>,
+}
+
+/// What tells whether a file changed without reading it.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct Stamp {
+    mtime: SystemTime,
+    size: u64,
+}
+
+impl Stamp {
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+
+        Some(Self {
+            mtime: metadata.modified().ok()?,
+            size: metadata.len(),
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub(super) enum CachedEntry {
+    Parsed(Box<Entry>),
+    /// Why the file was skipped, for `--debug`.
+    Skipped(String),
+}
+
+impl IndexCache {
+    pub fn new(locales: &[String], desktops: &[String]) -> Self {
+        Self {
+            version: VERSION,
+            locales: locales.to_vec(),
+            desktops: desktops.to_vec(),
+            dirs: Vec::new(),
+        }
+    }
+
+    /// Reads the cache, or gives an empty one when it is missing, broken or
+    /// was built for other locales or desktops.
+    pub fn load(locales: &[String], desktops: &[String]) -> Self {
+        Self::load_from(&Self::path(), locales, desktops)
+    }
+
+    fn load_from(path: &Path, locales: &[String], desktops: &[String]) -> Self {
+        let cache = fs::read(path)
+            .ok()
+            .and_then(|it| serde_json::from_slice::<IndexCache>(&it).ok());
+
+        match cache {
+            Some(cache)
+                if cache.version == VERSION
+                    && cache.locales == locales
+                    && cache.desktops == desktops =>
+            {
+                cache
+            }
+            _ => Self::new(locales, desktops),
+        }
+    }
+
+    /// Returns the files of `dir`, from the cache as far as it is still up to
+    /// date and parsed again otherwise. Either way they end up in `into`.
+    pub fn files(&self, dir: &Path, into: &mut IndexCache) -> Vec<CachedFile> {
+        let previous = self.dirs.iter().find(|it| it.path == dir);
+
+        let cached = match previous {
+            Some(previous) if previous.is_fresh() => {
+                previous.refresh(&into.locales, &into.desktops)
+            }
+            _ => CachedDir::scan(dir, previous, &into.locales, &into.desktops),
+        };
+
+        let files = cached.files.clone();
+        into.dirs.push(cached);
+        files
+    }
+
+    /// Whether `other` holds the same directories in the same state, in which
+    /// case writing it back can be skipped.
+    pub fn same_as(&self, other: &IndexCache) -> bool {
+        self.version == other.version
+            && self.locales == other.locales
+            && self.desktops == other.desktops
+            && self.dirs.len() == other.dirs.len()
+            && self
+                .dirs
+                .iter()
+                .zip(&other.dirs)
+                .all(|(a, b)| {
+                    a.path == b.path
+                        && a.mtimes == b.mtimes
+                        && a.scanned == b.scanned
+                        && a.same_files(b)
+                })
+    }
+
+    /// Writes the cache through a temporary file, so that a yappla started at
+    /// the same time never reads half of it.
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&Self::path())
+    }
+
+    fn save_to(&self, path: &Path) -> io::Result<()> {
+        let temporary = path.with_extension(format!("json.{}", std::process::id()));
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(&temporary, serde_json::to_vec(self)?)?;
+        fs::rename(&temporary, path)
+    }
+
+    fn path() -> PathBuf {
+        Config::cache_dir().join("apps.json")
+    }
+}
+
+impl CachedFile {
+    /// Reuses `previous` when the file is unchanged since it was parsed.
+    fn load(
+        id: String,
+        path: PathBuf,
+        previous: Option<&CachedFile>,
+        locales: &[String],
+        desktops: &[String],
+    ) -> Self {
+        let stamp = Stamp::of(&path);
+
+        if let Some(previous) = previous
+            && previous.id == id
+            && stamp.is_some()
+            && previous.stamp == stamp
+        {
+            return previous.clone();
+        }
+
+        let entry = match AppsMode::load_entry(&id, &path, locales, desktops) {
+            Ok(entry) => CachedEntry::Parsed(Box::new(entry)),
+            Err(reason) => CachedEntry::Skipped(reason.to_string()),
+        };
+
+        CachedFile {
+            id,
+            path,
+            entry,
+            stamp,
+        }
+    }
+}
+
+impl CachedDir {
+    /// Lists `dir` again, reusing the unchanged files of `previous`.
+    fn scan(
+        dir: &Path,
+        previous: Option<&CachedDir>,
+        locales: &[String],
+        desktops: &[String],
+    ) -> Self {
+        let scanned = SystemTime::now();
+        let mut dirs = Vec::new();
+
+        let files = AppsMode::get_desktop_files(dir, &mut dirs)
+            .into_iter()
+            .map(|(id, path)| {
+                let old = previous.and_then(|it| it.files.iter().find(|it| it.path == path));
+                CachedFile::load(id, path, old, locales, desktops)
+            })
+            .collect();
+
+        Self {
+            path: dir.to_path_buf(),
+            mtimes: dirs
+                .into_iter()
+                .filter_map(|it| mtime(&it).map(|mtime| (it, mtime)))
+                .collect(),
+            scanned,
+            files,
+        }
+    }
+
+    /// Keeps the listing, parsing the files that changed since.
+    fn refresh(&self, locales: &[String], desktops: &[String]) -> Self {
+        let files = self
+            .files
+            .iter()
+            .map(|it| CachedFile::load(it.id.clone(), it.path.clone(), Some(it), locales, desktops))
+            .collect();
+
+        Self {
+            path: self.path.clone(),
+            mtimes: self.mtimes.clone(),
+            scanned: self.scanned,
+            files,
+        }
+    }
+
+    fn same_files(&self, other: &CachedDir) -> bool {
+        self.files.len() == other.files.len()
+            && self
+                .files
+                .iter()
+                .zip(&other.files)
+                .all(|(a, b)| a.path == b.path && a.stamp == b.stamp)
+    }
+
+    /// A new subdirectory changes the mtime of its parent, so checking the
+    /// known ones is enough.
+    fn is_fresh(&self) -> bool {
+        !self.mtimes.is_empty()
+            && self.mtimes.iter().all(|(path, cached)| {
+                mtime(path).as_ref() == Some(cached)
+                    && self
+                        .scanned
+                        .duration_since(*cached)
+                        .is_ok_and(|it| it >= MTIME_GRANULARITY)
+            })
+    }
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|it| it.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{self, File, OpenOptions},
+        io::Write,
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    };
+
+    use super::{CachedEntry, CachedFile, IndexCache, VERSION};
+    use crate::menu_item_model::MenuItemModel;
+
+    /// A directory under the system temp directory, removed when dropped.
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("yappla-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn write(&self, file: &str, name: &str) {
+            let content = format!("[Desktop Entry]\nType=Application\nName={}\nExec=true\n", name);
+            fs::write(self.0.join(file), content).unwrap();
+        }
+
+        /// Sets the mtime of the directory to `minutes` ago, so that changes
+        /// show regardless of the timestamp granularity of the file system.
+        fn set_mtime(&self, minutes: u64) {
+            let mtime = SystemTime::now() - Duration::from_secs(minutes * 60);
+            File::open(&self.0).unwrap().set_modified(mtime).unwrap();
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn locales() -> Vec<String> {
+        vec!["en".to_string()]
+    }
+
+    fn desktops() -> Vec<String> {
+        vec!["GNOME".to_string()]
+    }
+
+    fn names(files: &[CachedFile]) -> Vec<String> {
+        let mut names: Vec<String> = files
+            .iter()
+            .filter_map(|it| match &it.entry {
+                CachedEntry::Parsed(entry) => Some(entry.name().clone()),
+                CachedEntry::Skipped(_) => None,
+            })
+            .collect();
+
+        names.sort();
+        names
+    }
+
+    /// Lists `dir` through `cache` and returns the cache to use next time.
+    fn list(cache: &IndexCache, dir: &Path) -> (Vec<String>, IndexCache) {
+        let mut updated = IndexCache::new(&locales(), &desktops());
+        let files = cache.files(dir, &mut updated);
+
+        (names(&files), updated)
+    }
+
+    #[test]
+    fn added_file() {
+        let dir = TestDir::new("cache-added");
+        dir.write("a.desktop", "A");
+        dir.set_mtime(60);
+
+        let (names, cache) = list(&IndexCache::new(&locales(), &desktops()), &dir.0);
+        assert_eq!(names, ["A"]);
+
+        dir.write("b.desktop", "B");
+        dir.set_mtime(30);
+
+        let (names, updated) = list(&cache, &dir.0);
+        assert_eq!(names, ["A", "B"]);
+        assert!(!updated.same_as(&cache));
+    }
+
+    #[test]
+    fn removed_file() {
+        let dir = TestDir::new("cache-removed");
+        dir.write("a.desktop", "A");
+        dir.write("b.desktop", "B");
+        dir.set_mtime(60);
+
+        let (_, cache) = list(&IndexCache::new(&locales(), &desktops()), &dir.0);
+        fs::remove_file(dir.0.join("a.desktop")).unwrap();
+        dir.set_mtime(30);
+
+        let (names, updated) = list(&cache, &dir.0);
+        assert_eq!(names, ["B"]);
+        assert!(!updated.same_as(&cache));
+    }
+
+    #[test]
+    fn file_edited_in_place() {
+        let dir = TestDir::new("cache-edited");
+        dir.write("a.desktop", "A");
+        dir.set_mtime(60);
+
+        let (_, cache) = list(&IndexCache::new(&locales(), &desktops()), &dir.0);
+
+        // Rewriting the file in place leaves the directory alone.
+        let mut file = OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .open(dir.0.join("a.desktop"))
+            .unwrap();
+        write!(file, "[Desktop Entry]\nType=Application\nName=Edited\nExec=true\n").unwrap();
+        drop(file);
+
+        assert!(cache.dirs[0].is_fresh());
+
+        let (names, updated) = list(&cache, &dir.0);
+        assert_eq!(names, ["Edited"]);
+        assert!(!updated.same_as(&cache));
+    }
+
+    #[test]
+    fn unchanged_files_are_reused() {
+        let dir = TestDir::new("cache-unchanged");
+        dir.write("a.desktop", "A");
+        dir.set_mtime(60);
+
+        let (_, cache) = list(&IndexCache::new(&locales(), &desktops()), &dir.0);
+        let (names, updated) = list(&cache, &dir.0);
+
+        assert_eq!(names, ["A"]);
+        assert!(updated.same_as(&cache));
+    }
+
+    #[test]
+    fn changed_while_scanning() {
+        let dir = TestDir::new("cache-recent");
+        dir.write("a.desktop", "A");
+
+        // The directory changed less than a second before the scan, another
+        // file could have been added after it without changing the mtime.
+        let (_, cache) = list(&IndexCache::new(&locales(), &desktops()), &dir.0);
+        assert!(!cache.dirs[0].is_fresh());
+
+        dir.set_mtime(60);
+
+        let (_, cache) = list(&cache, &dir.0);
+        assert!(cache.dirs[0].is_fresh());
+    }
+
+    #[test]
+    fn load_checks_version_locales_and_desktops() {
+        let dir = TestDir::new("cache-load");
+        let cache_dir = TestDir::new("cache-load-json");
+        let path = cache_dir.0.join("apps.json");
+        dir.write("a.desktop", "A");
+
+        let (_, cache) = list(&IndexCache::new(&locales(), &desktops()), &dir.0);
+        cache.save_to(&path).unwrap();
+
+        let loaded = IndexCache::load_from(&path, &locales(), &desktops());
+        assert!(loaded.same_as(&cache));
+
+        let other_locales = IndexCache::load_from(&path, &["de".to_string()], &desktops());
+        assert!(other_locales.dirs.is_empty());
+
+        let other_desktops = IndexCache::load_from(&path, &locales(), &["KDE".to_string()]);
+        assert!(other_desktops.dirs.is_empty());
+
+        let content = fs::read(&path).unwrap();
+        let mut json: serde_json::Value = serde_json::from_slice(&content).unwrap();
+        json["version"] = (VERSION - 1).into();
+        fs::write(&path, serde_json::to_vec(&json).unwrap()).unwrap();
+
+        let old_version = IndexCache::load_from(&path, &locales(), &desktops());
+        assert!(old_version.dirs.is_empty());
+    }
+}
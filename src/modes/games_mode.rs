@@ -0,0 +1,389 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    env::home_dir,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use glib::object::Cast;
+use relm4::gtk::gio::ListModel;
+
+use crate::{
+    frecency::Frecency,
+    index_list::{Index, IndexList},
+    launch,
+    menu_item_model::{self, ActionError, MenuItemModel},
+    modes::mode::Mode,
+    search::{Searchable, Searcher},
+    vdf,
+};
+
+/// App ids of Steam tools that show up in `steamapps` next to games.
+const STEAM_TOOLS: [&str; 4] = [
+    "228980",  // Steamworks Common Redistributables
+    "1070560", // Steam Linux Runtime 1.0 (scout)
+    "1391110", // Steam Linux Runtime 2.0 (soldier)
+    "1628350", // Steam Linux Runtime 3.0 (sniper)
+];
+
+pub struct GamesMode {
+    games: Vec<Game>,
+    indecies_buffer: RefCell<Vec<(u32, f64)>>,
+    model: IndexList,
+    frecency: Frecency,
+}
+
+struct Game {
+    app_id: String,
+    name: String,
+    lower_name: String,
+    flatpak: bool,
+}
+
+/// A Steam installation. The flatpak one has to be launched through
+/// `flatpak run`, as its `steam` binary is not on `$PATH`.
+struct SteamRoot {
+    path: PathBuf,
+    flatpak: bool,
+}
+
+impl GamesMode {
+    pub fn new() -> Self {
+        let mut games = Vec::new();
+        let mut seen_ids = HashSet::new();
+        let mut seen_libraries = HashSet::new();
+
+        for root in Self::get_steam_roots() {
+            for library in Self::get_library_folders(&root.path) {
+                let canonical = fs::canonicalize(&library).unwrap_or(library.clone());
+
+                if !seen_libraries.insert(canonical) {
+                    continue;
+                }
+
+                for (app_id, name) in Self::get_installed_apps(&library) {
+                    if STEAM_TOOLS.contains(&app_id.as_str())
+                        || name.starts_with("Proton")
+                        || name.starts_with("Steam Linux Runtime")
+                    {
+                        continue;
+                    }
+
+                    if !seen_ids.insert(app_id.clone()) {
+                        continue;
+                    }
+
+                    games.push(Game {
+                        lower_name: name.to_lowercase(),
+                        app_id,
+                        name,
+                        flatpak: root.flatpak,
+                    });
+                }
+            }
+        }
+
+        games.sort_by(|a, b| a.lower_name.cmp(&b.lower_name));
+
+        Self {
+            indecies_buffer: RefCell::new(Vec::with_capacity(games.len())),
+            model: IndexList::with_capacity(games.len()),
+            frecency: Frecency::load("games"),
+            games,
+        }
+    }
+
+    fn get_steam_roots() -> Vec<SteamRoot> {
+        let home = match home_dir() {
+            Some(home) => home,
+            None => return Vec::new(),
+        };
+
+        let mut roots = Vec::new();
+        let mut seen = HashSet::new();
+
+        let candidates = [
+            (home.join(".steam/steam"), false),
+            (home.join(".local/share/Steam"), false),
+            (home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"), true),
+        ];
+
+        // `~/.steam/steam` is usually a symlink to `~/.local/share/Steam`.
+        for (path, flatpak) in candidates {
+            if let Ok(canonical) = fs::canonicalize(&path)
+                && seen.insert(canonical)
+            {
+                roots.push(SteamRoot { path, flatpak });
+            }
+        }
+
+        roots
+    }
+
+    /// Reads `steamapps/libraryfolders.vdf`. The root itself is always a
+    /// library, even when the file is missing.
+    fn get_library_folders(root: &Path) -> Vec<PathBuf> {
+        let mut libraries = vec![root.to_path_buf()];
+
+        let content = match fs::read_to_string(root.join("steamapps/libraryfolders.vdf")) {
+            Ok(content) => content,
+            Err(_) => return libraries,
+        };
+
+        let document = match vdf::parse(&content) {
+            Ok(document) => document,
+            Err(err) => {
+                eprintln!("{}", err);
+                return libraries;
+            }
+        };
+
+        libraries.extend(Self::library_paths(&document));
+        libraries
+    }
+
+    /// The libraries listed in a parsed `libraryfolders.vdf`.
+    fn library_paths(document: &vdf::Value) -> Vec<PathBuf> {
+        let folders = match document.get("libraryfolders") {
+            Some(folders) => folders,
+            None => return Vec::new(),
+        };
+
+        folders
+            .entries()
+            .iter()
+            // Libraries have numeric keys, older files also have keys like
+            // `TimeNextStatsReport` next to them.
+            .filter(|(key, _)| key.parse::<u32>().is_ok())
+            .filter_map(|(_, folder)| {
+                // Newer files have `"0" { "path" "..." }`, older ones `"1" "..."`.
+                folder
+                    .get("path")
+                    .and_then(vdf::Value::as_str)
+                    .or(folder.as_str())
+            })
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    fn get_installed_apps(library: &Path) -> Vec<(String, String)> {
+        let entries = match fs::read_dir(library.join("steamapps")) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+
+        let mut apps = Vec::new();
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+
+            let is_manifest = path
+                .file_name()
+                .and_then(|it| it.to_str())
+                .is_some_and(|it| it.starts_with("appmanifest_") && it.ends_with(".acf"));
+
+            if !is_manifest {
+                continue;
+            }
+
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(_) => continue,
+            };
+
+            let document = match vdf::parse(&content) {
+                Ok(document) => document,
+                Err(err) => {
+                    eprintln!("{}: {}", path.display(), err);
+                    continue;
+                }
+            };
+
+            apps.extend(Self::manifest_app(&document));
+        }
+
+        apps
+    }
+
+    /// `(appid, name)` of a parsed `appmanifest_*.acf`.
+    fn manifest_app(document: &vdf::Value) -> Option<(String, String)> {
+        let state = document.get("AppState")?;
+        let app_id = state.get("appid").and_then(vdf::Value::as_str)?;
+        let name = state.get("name").and_then(vdf::Value::as_str)?;
+
+        Some((app_id.to_string(), name.to_string()))
+    }
+}
+
+impl Mode for GamesMode {
+    fn search(&self, query: String) -> ListModel {
+        if query.is_empty() {
+            return self.filled_model();
+        }
+
+        let query_lower = query.to_lowercase();
+
+        let searcher = Searcher::new(&self.games);
+        let entries = searcher.search(&query_lower).map(|(i, score)| {
+            (i, score * self.frecency.boost(&self.games[i as usize].app_id))
+        });
+
+        let mut indecies_buffer = self.indecies_buffer.borrow_mut();
+        indecies_buffer.clear();
+        indecies_buffer.extend(entries);
+        indecies_buffer.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        self.model.set_indecies(indecies_buffer.iter().map(|it| it.0));
+        self.model()
+    }
+
+    fn filled_model(&self) -> ListModel {
+        let mut indices: Vec<u32> = (0..(self.games.len())).map(|i| i as u32).collect();
+        self.frecency
+            .sort(&mut indices, |i| &self.games[i as usize].app_id);
+
+        self.model.set_indecies(indices);
+        self.model()
+    }
+
+    fn get_menu_item_model<'a>(&'a self, item: &Index) -> &'a dyn MenuItemModel {
+        &self.games[item.index() as usize]
+    }
+
+    fn model(&self) -> ListModel {
+        self.model.clone().upcast()
+    }
+
+    fn activate(&self, item: &Index) -> bool {
+        let game = &self.games[item.index() as usize];
+        let result = game.run_action();
+
+        if result.is_ok() {
+            self.frecency.record(&game.app_id);
+        }
+
+        menu_item_model::finish(result)
+    }
+}
+
+impl Searchable for Game {
+    fn score(&self, request: &str) -> f64 {
+        self.lower_name.as_str().score(request)
+    }
+}
+
+impl MenuItemModel for Game {
+    fn name(&self) -> &String {
+        &self.name
+    }
+
+    fn run_action(&self) -> Result<(), ActionError> {
+        let url = format!("steam://rungameid/{}", self.app_id);
+
+        let mut command = if self.flatpak {
+            let mut command = Command::new("flatpak");
+            command.args(["run", "com.valvesoftware.Steam"]);
+            command
+        } else {
+            Command::new("steam")
+        };
+
+        command.arg(&url);
+
+        launch::spawn_detached(&mut command).map_err(|err| ActionError {
+            command: format!("steam {}", url),
+            error: format!("Failed to launch {}", self.name),
+            cause: err,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::GamesMode;
+    use crate::vdf;
+
+    #[test]
+    fn library_paths_of_old_format() {
+        let document = vdf::parse(
+            r#"
+            "LibraryFolders"
+            {
+                "TimeNextStatsReport"  "1700000000"
+                "ContentStatsID"       "-4242424242424242424"
+                "1"                    "/mnt/games/SteamLibrary"
+                "2"                    "/home/user/more games"
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            GamesMode::library_paths(&document),
+            [
+                PathBuf::from("/mnt/games/SteamLibrary"),
+                PathBuf::from("/home/user/more games"),
+            ]
+        );
+    }
+
+    #[test]
+    fn library_paths_of_new_format() {
+        let document = vdf::parse(
+            r#"
+            "libraryfolders"
+            {
+                "0"
+                {
+                    "path"      "/home/user/.local/share/Steam"
+                    "label"     ""
+                    "apps"
+                    {
+                        "228980"    "468472709"
+                    }
+                }
+                "1"
+                {
+                    "path"      "/mnt/games/SteamLibrary"
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            GamesMode::library_paths(&document),
+            [
+                PathBuf::from("/home/user/.local/share/Steam"),
+                PathBuf::from("/mnt/games/SteamLibrary"),
+            ]
+        );
+    }
+
+    #[test]
+    fn manifest_app() {
+        let document = vdf::parse(
+            r#"
+            "AppState"
+            {
+                "appid"     "620"
+                "Universe"  "1"
+                "name"      "Portal 2"
+                "StateFlags"    "4"
+                "installdir"    "Portal 2"
+            }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            GamesMode::manifest_app(&document),
+            Some(("620".to_string(), "Portal 2".to_string()))
+        );
+        assert_eq!(GamesMode::manifest_app(&vdf::parse(r#""AppState" {}"#).unwrap()), None);
+    }
+}
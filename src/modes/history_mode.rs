@@ -0,0 +1,437 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    env::{self, home_dir},
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::UNIX_EPOCH,
+};
+
+use glib::object::Cast;
+use relm4::gtk::gio::ListModel;
+
+use crate::{
+    index_list::{Index, IndexList},
+    launch,
+    menu_item_model::{ActionError, MenuItemModel},
+    modes::mode::Mode,
+    search::{Searchable, Searcher},
+    terminal::Terminal,
+};
+
+pub struct HistoryMode {
+    entries: Vec<HistoryEntry>,
+    indecies_buffer: RefCell<Vec<(u32, f64)>>,
+    model: IndexList,
+}
+
+struct HistoryEntry {
+    command: String,
+    lower: String,
+    execute: bool,
+}
+
+/// A command read from a history file. `timestamp` is `None` when the shell
+/// didn't record one, `position` keeps the order inside the file.
+struct Record {
+    timestamp: Option<u64>,
+    position: usize,
+    command: String,
+}
+
+impl HistoryMode {
+    pub fn new(execute: bool) -> Self {
+        let home = home_dir().unwrap_or_else(|| PathBuf::from("/"));
+
+        let mut records = Vec::new();
+
+        records.extend(Self::read(&home.join(".bash_history"), parse_bash));
+
+        let zdotdir = env::var_os("ZDOTDIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.clone());
+
+        records.extend(Self::read(&zdotdir.join(".zsh_history"), parse_zsh));
+        records.extend(Self::read(&zdotdir.join(".histfile"), parse_zsh));
+
+        let data_home = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".local/share"));
+
+        records.extend(Self::read(&data_home.join("fish/fish_history"), parse_fish));
+
+        let entries: Vec<HistoryEntry> = newest_unique(records)
+            .into_iter()
+            .map(|command| HistoryEntry {
+                lower: command.to_lowercase(),
+                command,
+                execute,
+            })
+            .collect();
+
+        Self {
+            indecies_buffer: RefCell::new(Vec::with_capacity(entries.len())),
+            model: IndexList::with_capacity(entries.len()),
+            entries,
+        }
+    }
+
+    /// Reads a history file with `parser`. Records without their own timestamp
+    /// get the file's modification time, so that histories of different shells
+    /// interleave by the last time each shell was used.
+    fn read(path: &Path, parser: fn(&[u8]) -> Vec<Record>) -> Vec<Record> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(_) => return Vec::new(),
+        };
+
+        let modified = fs::metadata(path)
+            .and_then(|it| it.modified())
+            .ok()
+            .and_then(|it| it.duration_since(UNIX_EPOCH).ok())
+            .map(|it| it.as_secs());
+
+        let mut records = parser(&bytes);
+
+        for record in records.iter_mut() {
+            record.timestamp = record.timestamp.or(modified);
+        }
+
+        records
+    }
+}
+
+/// Commands newest first, each only once at the time it was last used.
+/// Records without a timestamp are ordered by their position in the file,
+/// which is chronological for every shell.
+fn newest_unique(mut records: Vec<Record>) -> Vec<String> {
+    records.sort_by(|a, b| {
+        b.timestamp
+            .cmp(&a.timestamp)
+            .then_with(|| b.position.cmp(&a.position))
+    });
+
+    let mut seen = HashSet::new();
+
+    records
+        .into_iter()
+        .filter(|it| !it.command.trim().is_empty())
+        .filter(|it| seen.insert(it.command.clone()))
+        .map(|it| it.command)
+        .collect()
+}
+
+/// Parses `~/.bash_history`. Lines like `#1700000000` are timestamps written
+/// when `HISTTIMEFORMAT` is set and apply to the following command.
+fn parse_bash(content: &[u8]) -> Vec<Record> {
+    let content = String::from_utf8_lossy(content);
+    let mut records = Vec::new();
+    let mut timestamp = None;
+
+    for line in content.lines() {
+        if let Some(ts) = line.strip_prefix('#').and_then(|it| it.parse().ok()) {
+            timestamp = Some(ts);
+            continue;
+        }
+
+        // A timestamp still applies to the command after a blank line.
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        records.push(Record {
+            timestamp: timestamp.take(),
+            position: records.len(),
+            command: line.to_string(),
+        });
+    }
+
+    records
+}
+
+/// Parses zsh history, both plain and `EXTENDED_HISTORY` lines of the form
+/// `: <timestamp>:<duration>;<command>`. Multiline commands are stored with a
+/// trailing backslash on every line but the last one.
+fn parse_zsh(content: &[u8]) -> Vec<Record> {
+    let content = String::from_utf8_lossy(&unmetafy(content)).into_owned();
+    let mut records = Vec::new();
+    let mut lines = content.lines();
+
+    while let Some(line) = lines.next() {
+        let mut command = line.to_string();
+
+        while command.ends_with('\\') {
+            match lines.next() {
+                Some(next) => {
+                    command.pop();
+                    command.push('\n');
+                    command.push_str(next);
+                }
+                None => break,
+            }
+        }
+
+        let (timestamp, command) = match parse_zsh_extended(&command) {
+            Some((timestamp, command)) => (Some(timestamp), command.to_string()),
+            None => (None, command),
+        };
+
+        records.push(Record {
+            timestamp,
+            position: records.len(),
+            command,
+        });
+    }
+
+    records
+}
+
+fn parse_zsh_extended(line: &str) -> Option<(u64, &str)> {
+    let (meta, command) = line.strip_prefix(": ")?.split_once(';')?;
+    let (timestamp, _duration) = meta.split_once(':')?;
+    Some((timestamp.trim().parse().ok()?, command))
+}
+
+/// zsh "metafies" special bytes as `0x83, byte ^ 0x20` before writing them,
+/// which breaks multibyte UTF-8 sequences until they are decoded back.
+fn unmetafy(content: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(content.len());
+    let mut bytes = content.iter();
+
+    while let Some(byte) = bytes.next() {
+        if *byte == 0x83 {
+            if let Some(next) = bytes.next() {
+                result.push(next ^ 0x20);
+            }
+        } else {
+            result.push(*byte);
+        }
+    }
+
+    result
+}
+
+/// Parses fish history, a YAML-like list of `- cmd: ...` items followed by
+/// indented `when: <timestamp>` and `paths:` keys.
+fn parse_fish(content: &[u8]) -> Vec<Record> {
+    let content = String::from_utf8_lossy(content);
+    let mut records: Vec<Record> = Vec::new();
+
+    for line in content.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            records.push(Record {
+                timestamp: None,
+                position: records.len(),
+                command: unescape_fish(command),
+            });
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ")
+            && let Some(record) = records.last_mut()
+        {
+            record.timestamp = when.trim().parse().ok();
+        }
+    }
+
+    records
+}
+
+fn unescape_fish(command: &str) -> String {
+    let mut result = String::with_capacity(command.len());
+    let mut chars = command.chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('\\') => result.push('\\'),
+                Some(other) => {
+                    result.push('\\');
+                    result.push(other);
+                }
+                None => result.push('\\'),
+            },
+            _ => result.push(char),
+        }
+    }
+
+    result
+}
+
+impl Mode for HistoryMode {
+    fn search(&self, query: String) -> ListModel {
+        if query.is_empty() {
+            return self.filled_model();
+        }
+
+        let query_lower = query.to_lowercase();
+
+        let searcher = Searcher::new(&self.entries);
+        let entries = searcher.search(&query_lower);
+
+        // Entries are stored newest first, so equal scores fall back to
+        // recency instead of whatever order the sort leaves them in.
+        let mut indecies_buffer = self.indecies_buffer.borrow_mut();
+        indecies_buffer.clear();
+        indecies_buffer.extend(entries);
+        indecies_buffer.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        self.model.set_indecies(indecies_buffer.iter().map(|it| it.0));
+        self.model()
+    }
+
+    fn filled_model(&self) -> ListModel {
+        self.model
+            .set_indecies((0..(self.entries.len())).map(|i| i as u32));
+        self.model()
+    }
+
+    fn get_menu_item_model<'a>(&'a self, item: &Index) -> &'a dyn MenuItemModel {
+        &self.entries[item.index() as usize]
+    }
+
+    fn model(&self) -> ListModel {
+        self.model.clone().upcast()
+    }
+}
+
+impl Searchable for HistoryEntry {
+    fn score(&self, request: &str) -> f64 {
+        self.lower.as_str().score(request)
+    }
+}
+
+impl MenuItemModel for HistoryEntry {
+    fn name(&self) -> &String {
+        &self.command
+    }
+
+    fn run_action(&self) -> Result<(), ActionError> {
+        if !self.execute {
+            println!("{}", self.command);
+            return Ok(());
+        }
+
+        let shell = env::var("SHELL").unwrap_or_else(|_| "sh".to_string());
+
+        // Keep the terminal open with an interactive shell once the command
+        // finishes, otherwise its output disappears with the window.
+        let argv = [
+            shell.clone(),
+            "-c".to_string(),
+            format!("{}\nexec {}", self.command, shell),
+        ];
+
+        let argv = match Terminal::detect() {
+            Some(terminal) => terminal.wrap(&argv),
+            None => {
+                return Err(ActionError {
+                    command: self.command.clone(),
+                    error: "Failed to find a terminal emulator".to_string(),
+                    cause: io::Error::new(io::ErrorKind::NotFound, "no terminal configured"),
+                });
+            }
+        };
+
+        launch::spawn_detached(Command::new(&argv[0]).args(&argv[1..])).map_err(|err| {
+            ActionError {
+                command: argv.join(" "),
+                error: "Failed to run command in terminal".to_string(),
+                cause: err,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Record, newest_unique, parse_bash, parse_fish, parse_zsh};
+
+    fn commands(records: &[Record]) -> Vec<(Option<u64>, &str)> {
+        records
+            .iter()
+            .map(|it| (it.timestamp, it.command.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn bash() {
+        let history = b"ls -la\n\n#1700000000\ngit status\n#1700000100\n\ncargo build\n";
+        let records = parse_bash(history);
+
+        assert_eq!(
+            commands(&records),
+            [
+                (None, "ls -la"),
+                (Some(1700000000), "git status"),
+                (Some(1700000100), "cargo build"),
+            ]
+        );
+    }
+
+    #[test]
+    fn zsh_plain_and_extended() {
+        let records = parse_zsh(b"ls\n: 1700000000:0;git status\n: 1700000100:12;sleep 12\n");
+
+        assert_eq!(
+            commands(&records),
+            [
+                (None, "ls"),
+                (Some(1700000000), "git status"),
+                (Some(1700000100), "sleep 12"),
+            ]
+        );
+    }
+
+    #[test]
+    fn zsh_multiline() {
+        let records = parse_zsh(b": 1700000000:0;for i in 1 2; do\\\n  echo $i\\\ndone\nls\n");
+
+        assert_eq!(
+            commands(&records),
+            [
+                (Some(1700000000), "for i in 1 2; do\n  echo $i\ndone"),
+                (None, "ls"),
+            ]
+        );
+    }
+
+    #[test]
+    fn zsh_metafied() {
+        // `é` is 0xc3 0xa9, zsh writes 0xa9 as 0x83 0x89.
+        let records = parse_zsh(b"echo caf\xc3\x83\x89\n");
+
+        assert_eq!(commands(&records), [(None, "echo café")]);
+    }
+
+    #[test]
+    fn fish() {
+        let history = b"- cmd: ls\n  when: 1700000000\n\
+            - cmd: echo a\\nb \\\\n\n  when: 1700000100\n  paths:\n    - a\n";
+        let records = parse_fish(history);
+
+        assert_eq!(
+            commands(&records),
+            [(Some(1700000000), "ls"), (Some(1700000100), "echo a\nb \\n")]
+        );
+    }
+
+    #[test]
+    fn newest_first_without_duplicates() {
+        let record = |timestamp, position, command: &str| Record {
+            timestamp,
+            position,
+            command: command.to_string(),
+        };
+
+        let records = vec![
+            record(Some(100), 0, "ls"),
+            record(Some(300), 1, "git status"),
+            record(Some(200), 2, "ls"),
+            record(Some(300), 3, "cargo build"),
+            record(Some(50), 4, " "),
+        ];
+
+        assert_eq!(newest_unique(records), ["cargo build", "git status", "ls"]);
+    }
+}
+
@@ -1,3 +1,6 @@
 pub mod mode;
 pub mod echo_mode;
 pub mod apps_mode;
+pub mod history_mode;
+pub mod games_mode;
+pub mod open_with_mode;
\ No newline at end of file
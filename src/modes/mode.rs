@@ -7,4 +7,29 @@ pub trait Mode {
     fn filled_model(&self) -> gtk::gio:: ListModel;
     fn get_menu_item_model<'a>(&'a self, item: &Index) -> &'a dyn MenuItemModel;
     fn model(&self) -> gtk::gio:: ListModel;
+
+    /// Runs an item, on Enter or on a click on the focused row. Items that
+    /// don't quit yappla, like a category to open, return true when the list
+    /// has to be shown again.
+    fn activate(&self, item: &Index) -> bool {
+        self.get_menu_item_model(item).run();
+        false
+    }
+
+    /// Chars of the item's name matched by `query`, the text of the search
+    /// entry.
+    fn highlight(&self, item: &Index, query: &str) -> Option<Vec<usize>> {
+        self.get_menu_item_model(item).highlight(&query.to_lowercase())
+    }
+
+    /// Pins or unpins an item, on Ctrl+P. Returns whether the list changed.
+    fn toggle_pinned(&self, _item: &Index) -> bool {
+        false
+    }
+
+    /// Hides or shows an item again, on Ctrl+H. Returns whether the list
+    /// changed.
+    fn toggle_hidden(&self, _item: &Index) -> bool {
+        false
+    }
 }
\ No newline at end of file
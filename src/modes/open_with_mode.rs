@@ -0,0 +1,128 @@
+use std::{cell::RefCell, slice};
+
+use glib::object::Cast;
+use relm4::gtk::gio::ListModel;
+
+use crate::{
+    cli,
+    exec::Target,
+    index_list::{Index, IndexList},
+    menu_item_model::{self, MenuItemModel},
+    mimeapps,
+    modes::{
+        apps_mode::{AppsMode, Application, Entry},
+        mode::Mode,
+    },
+    search::Searcher,
+};
+
+/// Apps that can open one file or URL: the default handler from
+/// `mimeapps.list` first, then the added associations, then every other app
+/// listing the type in `MimeType`.
+pub struct OpenWithMode {
+    apps: Vec<Application>,
+    indecies_buffer: RefCell<Vec<(u32, f64)>>,
+    model: IndexList,
+    target: Target,
+}
+
+impl OpenWithMode {
+    pub fn new(file: &str) -> Self {
+        let target = Target::parse(file);
+        let mime = mimeapps::guess_type(&target);
+
+        if cli::has_flag("--debug") {
+            eprintln!("{} is {}", file, mime);
+        }
+
+        let files = mimeapps::list_files(&AppsMode::get_current_desktops());
+        let associations = mimeapps::associations(&mime, &files);
+        // Handlers of URL schemes and helpers are often `NoDisplay`.
+        let mut remaining: Vec<Application> = AppsMode::load_entries(true)
+            .into_iter()
+            .filter_map(|it| match it {
+                Entry::Application(app) => Some(*app),
+                Entry::Link(_) => None,
+            })
+            .collect();
+        let mut apps = Vec::new();
+
+        // Only the first installed default counts.
+        let default = associations
+            .defaults
+            .iter()
+            .find(|id| remaining.iter().any(|it| it.id == **id));
+
+        for id in default.into_iter().chain(&associations.added) {
+            if let Some(i) = remaining.iter().position(|it| it.id == *id) {
+                apps.push(remaining.swap_remove(i));
+            }
+        }
+
+        // Apps for the exact type come before those for a parent type, like a
+        // text editor for a Python script.
+        let mut handlers: Vec<(u8, Application)> = remaining
+            .into_iter()
+            .filter(|it| !associations.removed.contains(&it.id))
+            .filter_map(|it| {
+                if it.mime_types.contains(&mime) {
+                    Some((0, it))
+                } else if it.mime_types.iter().any(|it| mimeapps::handles(&mime, it)) {
+                    Some((1, it))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        handlers.sort_by_cached_key(|(rank, app)| (*rank, app.name().to_lowercase()));
+        apps.extend(handlers.into_iter().map(|(_, app)| app));
+
+        Self {
+            indecies_buffer: RefCell::new(Vec::with_capacity(apps.len())),
+            model: IndexList::with_capacity(apps.len()),
+            apps,
+            target,
+        }
+    }
+}
+
+impl Mode for OpenWithMode {
+    fn search(&self, query: String) -> ListModel {
+        if query.is_empty() {
+            return self.filled_model();
+        }
+
+        let query_lower = query.to_lowercase();
+
+        let searcher = Searcher::new(&self.apps);
+        let entries = searcher.search(&query_lower);
+
+        let mut indecies_buffer = self.indecies_buffer.borrow_mut();
+        indecies_buffer.clear();
+        indecies_buffer.extend(entries);
+        indecies_buffer.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        self.model.set_indecies(indecies_buffer.iter().map(|it| it.0));
+        self.model()
+    }
+
+    fn filled_model(&self) -> ListModel {
+        self.model
+            .set_indecies((0..(self.apps.len())).map(|i| i as u32));
+        self.model()
+    }
+
+    fn get_menu_item_model<'a>(&'a self, item: &Index) -> &'a dyn MenuItemModel {
+        &self.apps[item.index() as usize]
+    }
+
+    fn model(&self) -> ListModel {
+        self.model.clone().upcast()
+    }
+
+    fn activate(&self, item: &Index) -> bool {
+        let app = &self.apps[item.index() as usize];
+        menu_item_model::finish(app.launch(slice::from_ref(&self.target)));
+    }
+}
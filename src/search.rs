@@ -1,5 +1,29 @@
 use strsim::{jaro_winkler, normalized_levenshtein};
 
+use crate::config::{Config, SearchAlgorithm};
+
+const SCORE_MATCH: i32 = 16;
+const GAP_START: i32 = 3;
+const GAP_EXTENSION: i32 = 1;
+/// After whitespace, or at the start of the text.
+const BONUS_BOUNDARY_WHITE: i32 = 10;
+/// After `/`, `-`, `_`, `.` and the like.
+const BONUS_BOUNDARY_DELIMITER: i32 = 9;
+/// After other punctuation.
+const BONUS_BOUNDARY: i32 = 8;
+/// Punctuation itself, so that typing it is rewarded.
+const BONUS_NON_WORD: i32 = 8;
+/// An upper case letter after a lower case one, or a digit after a
+/// non-digit.
+const BONUS_CAMEL_123: i32 = 7;
+/// Least bonus of a char right after the previous match.
+const BONUS_CONSECUTIVE: i32 = 4;
+/// The first char of the pattern counts this much more, matches starting
+/// at a word are what people type.
+const BONUS_FIRST_CHAR_MULTIPLIER: i32 = 2;
+/// A match starting at the very beginning of the text.
+const BONUS_PREFIX: i32 = 8;
+
 pub struct Searcher<'a, T>
 where
     T: Searchable,
@@ -30,11 +54,284 @@ pub trait Searchable {
 
 impl Searchable for str {
     fn score(&self, request: &str) -> f64 {
-        if request.len() <= 3 {
-            jaro_winkler(self, request)
+        match Config::get().search {
+            SearchAlgorithm::Fuzzy => fuzzy_match(self, request).map_or(0.0, |it| it.score),
+            SearchAlgorithm::Strsim => {
+                if request.len() <= 3 {
+                    jaro_winkler(self, request)
+                } else {
+                    normalized_levenshtein(self, request)
+                        + self.contains(request).then(|| 0.5).unwrap_or(0.0)
+                }
+            }
+        }
+    }
+}
+
+/// Indices of the chars of `text` matched by `request`, for showing them in
+/// bold. Only the fuzzy matcher knows them.
+pub fn positions(text: &str, request: &str) -> Option<Vec<usize>> {
+    match Config::get().search {
+        SearchAlgorithm::Fuzzy => fuzzy_match(text, request).map(|it| it.positions),
+        SearchAlgorithm::Strsim => None,
+    }
+}
+
+/// A fuzzy match of a pattern in a text.
+#[derive(Debug, PartialEq)]
+pub struct Match {
+    /// From 0 to 1, 1 for a text that is the pattern.
+    pub score: f64,
+    /// Indices of the matched chars of the text, in order.
+    pub positions: Vec<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum CharClass {
+    White,
+    NonWord,
+    Delimiter,
+    Lower,
+    Upper,
+    Letter,
+    Number,
+}
+
+impl CharClass {
+    fn of(char: char) -> Self {
+        if char.is_whitespace() {
+            CharClass::White
+        } else if char.is_lowercase() {
+            CharClass::Lower
+        } else if char.is_uppercase() {
+            CharClass::Upper
+        } else if char.is_numeric() {
+            CharClass::Number
+        } else if char.is_alphabetic() {
+            CharClass::Letter
+        } else if "/,:;|-_.".contains(char) {
+            CharClass::Delimiter
         } else {
-            normalized_levenshtein(self, request)
-                + self.contains(request).then(|| 0.5).unwrap_or(0.0)
+            CharClass::NonWord
         }
     }
+
+    fn is_word(self) -> bool {
+        self > CharClass::Delimiter
+    }
 }
+
+/// Bonus for matching a char of class `current` that follows one of class
+/// `previous`.
+fn bonus(previous: CharClass, current: CharClass) -> i32 {
+    if current.is_word() {
+        match previous {
+            CharClass::White => return BONUS_BOUNDARY_WHITE,
+            CharClass::Delimiter => return BONUS_BOUNDARY_DELIMITER,
+            CharClass::NonWord => return BONUS_BOUNDARY,
+            _ => {}
+        }
+    }
+
+    match (previous, current) {
+        (CharClass::Lower, CharClass::Upper) => BONUS_CAMEL_123,
+        (previous, CharClass::Number) if previous != CharClass::Number => BONUS_CAMEL_123,
+        (_, CharClass::White) => BONUS_BOUNDARY_WHITE,
+        (_, CharClass::NonWord | CharClass::Delimiter) => BONUS_NON_WORD,
+        _ => 0,
+    }
+}
+
+/// Finds the chars of `pattern` in `text` in order, ignoring case, and
+/// scores the best way to do so like fzf: matches at word boundaries,
+/// camelCase humps and digits, consecutive runs and a prefix are rewarded,
+/// gaps between matches cost a little.
+pub fn fuzzy_match(text: &str, pattern: &str) -> Option<Match> {
+    let lower = |char: char| char.to_lowercase().next().unwrap_or(char);
+
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().map(lower).collect();
+
+    if pattern.is_empty() {
+        return None;
+    }
+
+    let lower_text: Vec<char> = text.iter().map(|it| lower(*it)).collect();
+
+    // Cheap check whether it matches at all, most texts don't.
+    let mut remaining = pattern.iter().peekable();
+
+    for char in &lower_text {
+        if remaining.peek() == Some(&char) {
+            remaining.next();
+        }
+    }
+
+    if remaining.peek().is_some() {
+        return None;
+    }
+
+    let (n, m) = (text.len(), pattern.len());
+    let mut previous = CharClass::White;
+    let bonuses: Vec<i32> = text
+        .iter()
+        .map(|it| {
+            let class = CharClass::of(*it);
+            let bonus = bonus(previous, class);
+            previous = class;
+            bonus
+        })
+        .collect();
+
+    // `scores[i][j]` is the best score of `pattern[..=i]` with `pattern[i]`
+    // matched at `text[j]`, `runs` the bonus of the consecutive run it ends
+    // and `from` where `pattern[i - 1]` was matched.
+    let none = i32::MIN / 2;
+    let mut scores = vec![vec![none; n]; m];
+    let mut runs = vec![vec![0; n]; m];
+    let mut from = vec![vec![0; n]; m];
+
+    for i in 0..m {
+        // Best `(score - gap penalty, j)` of the previous row for a gap of
+        // at least one char.
+        let mut gapped = (none, 0);
+
+        for j in i..n {
+            if i > 0 && j >= 2 && scores[i - 1][j - 2] > none {
+                gapped = if scores[i - 1][j - 2] - GAP_START >= gapped.0 - GAP_EXTENSION {
+                    (scores[i - 1][j - 2] - GAP_START, j - 2)
+                } else {
+                    (gapped.0 - GAP_EXTENSION, gapped.1)
+                };
+            } else if gapped.0 > none {
+                gapped.0 -= GAP_EXTENSION;
+            }
+
+            if lower_text[j] != pattern[i] {
+                continue;
+            }
+
+            if i == 0 {
+                let prefix = if j == 0 { BONUS_PREFIX } else { 0 };
+                scores[i][j] = SCORE_MATCH + bonuses[j] * BONUS_FIRST_CHAR_MULTIPLIER + prefix;
+                runs[i][j] = bonuses[j];
+                continue;
+            }
+
+            if j > 0 && scores[i - 1][j - 1] > none {
+                let run = runs[i - 1][j - 1].max(bonuses[j]);
+                let score = scores[i - 1][j - 1] + SCORE_MATCH + run.max(BONUS_CONSECUTIVE);
+
+                scores[i][j] = score;
+                runs[i][j] = run;
+                from[i][j] = j - 1;
+            }
+
+            if gapped.0 > none {
+                let score = gapped.0 + SCORE_MATCH + bonuses[j];
+
+                if score > scores[i][j] {
+                    scores[i][j] = score;
+                    runs[i][j] = bonuses[j];
+                    from[i][j] = gapped.1;
+                }
+            }
+        }
+    }
+
+    let (mut j, best) = scores[m - 1]
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by_key(|(_, score)| *score)?;
+
+    if best <= none {
+        return None;
+    }
+
+    let mut positions = vec![0; m];
+
+    for i in (0..m).rev() {
+        positions[i] = j;
+        j = from[i][j];
+    }
+
+    // What a pattern typed at the start of a word and matched in one run
+    // would get.
+    let perfect = SCORE_MATCH * m as i32
+        + BONUS_BOUNDARY_WHITE * (BONUS_FIRST_CHAR_MULTIPLIER + m as i32 - 1)
+        + BONUS_PREFIX;
+    let quality = (best as f64 / perfect as f64).clamp(0.0, 1.0);
+
+    // Among equally good matches, the shorter text wins.
+    let coverage = m as f64 / n as f64;
+
+    Some(Match {
+        score: quality * (0.9 + 0.1 * coverage),
+        positions,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fuzzy_match;
+
+    fn score(text: &str, pattern: &str) -> f64 {
+        fuzzy_match(text, pattern).map_or(0.0, |it| it.score)
+    }
+
+    fn positions(text: &str, pattern: &str) -> Vec<usize> {
+        fuzzy_match(text, pattern).unwrap().positions
+    }
+
+    #[test]
+    fn subsequence_only() {
+        assert!(fuzzy_match("Firefox", "fxo").is_none());
+        assert!(fuzzy_match("abc", "abcd").is_none());
+        assert!(fuzzy_match("Firefox", "").is_none());
+    }
+
+    #[test]
+    fn fox_finds_firefox() {
+        assert_eq!(positions("Firefox", "fox"), [0, 5, 6]);
+        assert!(score("Firefox", "fox") > 0.5);
+        assert!(score("Firefox", "fox") > score("Caffeine Proxy", "fox"));
+    }
+
+    #[test]
+    fn ignores_case() {
+        assert_eq!(positions("LibreOffice Calc", "calc"), [12, 13, 14, 15]);
+        assert_eq!(fuzzy_match("GIMP", "gimp"), fuzzy_match("gimp", "GIMP"));
+    }
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(positions("Visual Studio Code", "vsc"), [0, 7, 14]);
+        assert_eq!(positions("xfce4-terminal", "term"), [6, 7, 8, 9]);
+        assert!(score("Visual Studio Code", "vsc") > score("vistascope", "vsc"));
+    }
+
+    #[test]
+    fn camel_case() {
+        assert_eq!(positions("FooBar", "fb"), [0, 3]);
+        assert!(score("FooBar", "fb") > score("Foobar", "fb"));
+    }
+
+    #[test]
+    fn consecutive_runs() {
+        assert!(score("abcxx", "abc") > score("axbxc", "abc"));
+    }
+
+    #[test]
+    fn prefix_beats_mid_word() {
+        assert!(score("Terminal", "term") > score("Xterminal", "term"));
+        assert!(score("Calculator", "calc") > score("Recalc", "calc"));
+    }
+
+    #[test]
+    fn exact_match_is_best() {
+        assert_eq!(score("fox", "fox"), 1.0);
+        assert!(score("fox", "fox") > score("Firefox", "fox"));
+    }
+}
+
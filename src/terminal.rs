@@ -0,0 +1,86 @@
+use std::{env, path::Path};
+
+use crate::{config::Config, exec};
+
+/// Known terminal emulators and the arguments that go between the emulator
+/// and the command it should run. Emulators whose `-e` takes a single string
+/// instead of an argv are left out.
+const KNOWN_TERMINALS: [(&str, &[&str]); 15] = [
+    ("foot", &[]),
+    ("kitty", &[]),
+    ("alacritty", &["-e"]),
+    ("wezterm", &["start", "--"]),
+    ("ghostty", &["-e"]),
+    ("rio", &["-e"]),
+    ("gnome-terminal", &["--"]),
+    ("kgx", &["--"]),
+    ("ptyxis", &["--"]),
+    ("konsole", &["-e"]),
+    ("xfce4-terminal", &["-x"]),
+    ("mate-terminal", &["-x"]),
+    ("terminator", &["-x"]),
+    ("urxvt", &["-e"]),
+    ("xterm", &["-e"]),
+];
+
+pub struct Terminal {
+    prefix: Vec<String>,
+}
+
+impl Terminal {
+    /// Picks the terminal from the config, `xdg-terminal-exec`, `$TERMINAL`
+    /// or the first known emulator on `$PATH`, in that order.
+    pub fn detect() -> Option<Terminal> {
+        if let Some(command) = &Config::get().terminal
+            && !command.is_empty()
+        {
+            return Some(match command.as_slice() {
+                [program] => Self::with_convention(program),
+                _ => Terminal {
+                    prefix: command.clone(),
+                },
+            });
+        }
+
+        if exec::is_executable_in_path("xdg-terminal-exec") {
+            return Some(Terminal {
+                prefix: vec!["xdg-terminal-exec".to_string()],
+            });
+        }
+
+        if let Ok(program) = env::var("TERMINAL")
+            && !program.is_empty()
+        {
+            return Some(Self::with_convention(&program));
+        }
+
+        KNOWN_TERMINALS
+            .iter()
+            .find(|(program, _)| exec::is_executable_in_path(program))
+            .map(|(program, _)| Self::with_convention(program))
+    }
+
+    /// Returns the argv that runs `argv` inside this terminal.
+    pub fn wrap(&self, argv: &[String]) -> Vec<String> {
+        self.prefix.iter().chain(argv).cloned().collect()
+    }
+
+    /// Unknown emulators are assumed to follow xterm's `-e`.
+    fn with_convention(program: &str) -> Terminal {
+        let name = Path::new(program)
+            .file_name()
+            .and_then(|it| it.to_str())
+            .unwrap_or(program);
+
+        let args = KNOWN_TERMINALS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, args)| *args)
+            .unwrap_or(&["-e"]);
+
+        let mut prefix = vec![program.to_string()];
+        prefix.extend(args.iter().map(|it| it.to_string()));
+
+        Terminal { prefix }
+    }
+}
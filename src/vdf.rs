@@ -0,0 +1,280 @@
+use std::{fmt::Display, iter::Peekable, str::Chars};
+
+/// A node of Valve's KeyValues text format, used by Steam for
+/// `libraryfolders.vdf` and `appmanifest_*.acf` files.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// Looks up a child by key. Keys are case-insensitive, older Steam
+    /// versions wrote `LibraryFolders` where newer ones write `libraryfolders`.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.entries()
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    pub fn entries(&self) -> &[(String, Value)] {
+        match self {
+            Value::Object(entries) => entries,
+            Value::String(_) => &[],
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(string) => Some(string),
+            Value::Object(_) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "VDF parse error at line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    String(String),
+    Open,
+    Close,
+}
+
+struct Lexer<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(input: &'a str) -> Self {
+        Self {
+            chars: input.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.line,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        while let Some(&char) = self.chars.peek() {
+            if char == '\n' {
+                self.line += 1;
+                self.chars.next();
+            } else if char.is_whitespace() {
+                self.chars.next();
+            } else if char == '/' {
+                // `//` starts a comment until the end of the line.
+                let mut lookahead = self.chars.clone();
+                lookahead.next();
+
+                if lookahead.peek() != Some(&'/') {
+                    return;
+                }
+
+                while self.chars.peek().is_some_and(|it| *it != '\n') {
+                    self.chars.next();
+                }
+            } else if char == '[' {
+                // Platform conditionals like `[$WIN32]` are not needed here.
+                for char in self.chars.by_ref() {
+                    if char == ']' {
+                        break;
+                    }
+                }
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, ParseError> {
+        self.skip_whitespace_and_comments();
+
+        let char = match self.chars.next() {
+            Some(char) => char,
+            None => return Ok(None),
+        };
+
+        match char {
+            '{' => Ok(Some(Token::Open)),
+            '}' => Ok(Some(Token::Close)),
+            '"' => self.quoted().map(|it| Some(Token::String(it))),
+            _ => {
+                let mut string = char.to_string();
+
+                while let Some(&char) = self.chars.peek() {
+                    if char.is_whitespace() || matches!(char, '{' | '}' | '"') {
+                        break;
+                    }
+                    string.push(char);
+                    self.chars.next();
+                }
+
+                Ok(Some(Token::String(string)))
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, ParseError> {
+        let mut string = String::new();
+
+        loop {
+            match self.chars.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    Some(char) => string.push(char),
+                    None => return Err(self.error("unterminated escape sequence")),
+                },
+                Some(char) => {
+                    if char == '\n' {
+                        self.line += 1;
+                    }
+                    string.push(char);
+                }
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+}
+
+/// Parses a VDF document into its top level key/value pairs.
+pub fn parse(input: &str) -> Result<Value, ParseError> {
+    let mut lexer = Lexer::new(input);
+    let entries = parse_object(&mut lexer, false)?;
+    Ok(Value::Object(entries))
+}
+
+fn parse_object(lexer: &mut Lexer, nested: bool) -> Result<Vec<(String, Value)>, ParseError> {
+    let mut entries = Vec::new();
+
+    loop {
+        let key = match lexer.next_token()? {
+            Some(Token::String(key)) => key,
+            Some(Token::Close) if nested => return Ok(entries),
+            Some(Token::Close) => return Err(lexer.error("unexpected '}'")),
+            Some(Token::Open) => return Err(lexer.error("expected a key, found '{'")),
+            None if nested => return Err(lexer.error("unexpected end of file, expected '}'")),
+            None => return Ok(entries),
+        };
+
+        let value = match lexer.next_token()? {
+            Some(Token::String(value)) => Value::String(value),
+            Some(Token::Open) => Value::Object(parse_object(lexer, true)?),
+            Some(Token::Close) => return Err(lexer.error("expected a value, found '}'")),
+            None => return Err(lexer.error("unexpected end of file, expected a value")),
+        };
+
+        entries.push((key, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Value, parse};
+
+    fn string(value: &str) -> Value {
+        Value::String(value.to_string())
+    }
+
+    #[test]
+    fn old_libraryfolders() {
+        let document = parse(
+            r#"
+            "LibraryFolders"
+            {
+                "TimeNextStatsReport"   "1700000000"
+                "1"     "/mnt/games/SteamLibrary"
+            }
+            "#,
+        )
+        .unwrap();
+
+        let folders = document.get("libraryfolders").unwrap();
+        assert_eq!(folders.get("TimeNextStatsReport"), Some(&string("1700000000")));
+        assert_eq!(folders.get("1").and_then(Value::as_str), Some("/mnt/games/SteamLibrary"));
+    }
+
+    #[test]
+    fn new_libraryfolders() {
+        let document = parse(
+            r#"
+            "libraryfolders"
+            {
+                "0"
+                {
+                    "path"      "/home/user/.local/share/Steam"
+                    "apps"
+                    {
+                        "228980"    "468472709"
+                    }
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let folder = document.get("libraryfolders").and_then(|it| it.get("0")).unwrap();
+        assert_eq!(
+            folder.get("path").and_then(Value::as_str),
+            Some("/home/user/.local/share/Steam")
+        );
+        assert_eq!(
+            folder.get("apps"),
+            Some(&Value::Object(vec![("228980".to_string(), string("468472709"))]))
+        );
+    }
+
+    #[test]
+    fn appmanifest() {
+        let document = parse(
+            r#"
+            // Written by Steam.
+            "AppState"
+            {
+                "appid"     "620"
+                "name"      "Portal \"2\""
+                "installdir"    "Portal 2"
+                "UserConfig"
+                {
+                    "language"  "english" [$LINUX]
+                }
+            }
+            "#,
+        )
+        .unwrap();
+
+        let state = document.get("AppState").unwrap();
+        assert_eq!(state.get("appid"), Some(&string("620")));
+        assert_eq!(state.get("name"), Some(&string("Portal \"2\"")));
+        assert_eq!(
+            state.get("UserConfig").and_then(|it| it.get("language")),
+            Some(&string("english"))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse("\"key\" {\n\"a\" \"b\"\n").unwrap_err().line, 3);
+        assert!(parse("\"key\" }").is_err());
+        assert!(parse("\"key\"").is_err());
+    }
+}
@@ -0,0 +1,328 @@
+use std::fmt::Display;
+
+/// An element of a small XML document like the XDG menu files: elements,
+/// attributes and text. Comments, processing instructions and the doctype
+/// are skipped, namespaces and CDATA aren't supported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+impl Element {
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|it| match it {
+            Node::Element(element) => Some(element),
+            Node::Text(_) => None,
+        })
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The text inside the element, with surrounding whitespace removed.
+    pub fn text(&self) -> String {
+        let text: String = self
+            .children
+            .iter()
+            .filter_map(|it| match it {
+                Node::Text(text) => Some(text.as_str()),
+                Node::Element(_) => None,
+            })
+            .collect();
+
+        text.trim().to_string()
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "XML parse error at line {}: {}", self.line, self.message)
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            line: self.input[..self.position].matches('\n').count() + 1,
+            message: message.to_string(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.position..]
+    }
+
+    fn skip_until(&mut self, end: &str) -> Result<(), ParseError> {
+        match self.rest().find(end) {
+            Some(index) => {
+                self.position += index + end.len();
+                Ok(())
+            }
+            None => Err(self.error(&format!("missing {}", end))),
+        }
+    }
+
+    /// Skips whitespace, comments, `<?...?>` and `<!DOCTYPE ...>`.
+    fn skip_misc(&mut self) -> Result<(), ParseError> {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.position += rest.len() - trimmed.len();
+
+            if trimmed.starts_with("<!--") {
+                self.skip_until("-->")?;
+            } else if trimmed.starts_with("<?") {
+                self.skip_until("?>")?;
+            } else if trimmed.starts_with("<!") {
+                self.skip_until(">")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        let rest = self.rest();
+        let length = rest
+            .find(|it: char| it.is_whitespace() || matches!(it, '/' | '>' | '='))
+            .unwrap_or(rest.len());
+
+        if length == 0 {
+            return Err(self.error("expected a name"));
+        }
+
+        self.position += length;
+        Ok(rest[..length].to_string())
+    }
+
+    fn element(&mut self) -> Result<Element, ParseError> {
+        if !self.rest().starts_with('<') {
+            return Err(self.error("expected an element"));
+        }
+
+        self.position += 1;
+        let name = self.name()?;
+        let mut attributes = Vec::new();
+
+        loop {
+            self.skip_misc()?;
+
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                return Ok(Element {
+                    name,
+                    attributes,
+                    children: Vec::new(),
+                });
+            }
+
+            if self.rest().starts_with('>') {
+                self.position += 1;
+                break;
+            }
+
+            let key = self.name()?;
+            self.skip_misc()?;
+
+            if !self.rest().starts_with('=') {
+                return Err(self.error("expected = after an attribute name"));
+            }
+
+            self.position += 1;
+            self.skip_misc()?;
+
+            let quote = match self.rest().chars().next() {
+                Some(quote @ ('"' | '\'')) => quote,
+                _ => return Err(self.error("expected a quoted attribute value")),
+            };
+
+            self.position += 1;
+            let end = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| self.error("unterminated attribute value"))?;
+
+            attributes.push((key, unescape(&self.rest()[..end])));
+            self.position += end + 1;
+        }
+
+        let mut children = Vec::new();
+
+        loop {
+            let rest = self.rest();
+
+            if rest.starts_with("</") {
+                self.position += 2;
+                let closing = self.name()?;
+
+                if closing != name {
+                    return Err(self.error(&format!("</{}> closes <{}>", closing, name)));
+                }
+
+                self.skip_until(">")?;
+                return Ok(Element {
+                    name,
+                    attributes,
+                    children,
+                });
+            }
+
+            if rest.starts_with("<!--") || rest.starts_with("<?") {
+                self.skip_misc()?;
+            } else if rest.starts_with('<') {
+                children.push(Node::Element(self.element()?));
+            } else if rest.is_empty() {
+                return Err(self.error(&format!("<{}> is never closed", name)));
+            } else {
+                let length = rest.find('<').unwrap_or(rest.len());
+                children.push(Node::Text(unescape(&rest[..length])));
+                self.position += length;
+            }
+        }
+    }
+}
+
+/// Resolves the predefined entities and character references.
+fn unescape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+
+        let entity = &rest[1..end];
+        let char = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|it| u32::from_str_radix(it, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|it| it.parse().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match char {
+            Some(char) => {
+                result.push(char);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// Parses a document and returns its root element.
+pub fn parse(input: &str) -> Result<Element, ParseError> {
+    let mut parser = Parser { input, position: 0 };
+
+    parser.skip_misc()?;
+    let root = parser.element()?;
+    parser.skip_misc()?;
+
+    if !parser.rest().is_empty() {
+        return Err(parser.error("content after the root element"));
+    }
+
+    Ok(root)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Element, Node, parse};
+
+    #[test]
+    fn entities_and_char_refs() {
+        let root = parse(r#"<a t="&lt;&amp;&#x41;">x &gt; &#66; &unknown; &amp &quot;&apos;</a>"#)
+            .unwrap();
+
+        assert_eq!(root.attribute("t"), Some("<&A"));
+        assert_eq!(root.text(), "x > B &unknown; &amp \"'");
+    }
+
+    #[test]
+    fn comments_and_doctype() {
+        let root = parse(
+            r#"<?xml version="1.0"?>
+<!DOCTYPE Menu PUBLIC "-//freedesktop//DTD Menu 1.0//EN"
+ "http://www.freedesktop.org/standards/menu-spec/1.0/menu.dtd">
+<!-- comment with <Menu> inside -->
+<Menu>
+  <!-- <Name>Commented</Name> -->
+  <Name>Applications</Name>
+  <OnlyUnallocated/>
+  <Include><Category  >Game</Category></Include>
+</Menu>
+<!-- trailing -->
+"#,
+        )
+        .unwrap();
+
+        let names: Vec<&str> = root.elements().map(|it| it.name.as_str()).collect();
+        assert_eq!(names, ["Name", "OnlyUnallocated", "Include"]);
+        assert_eq!(root.elements().next().unwrap().text(), "Applications");
+
+        let include = root.elements().nth(2).unwrap();
+        assert_eq!(
+            include.children,
+            [Node::Element(Element {
+                name: "Category".to_string(),
+                attributes: Vec::new(),
+                children: vec![Node::Text("Game".to_string())],
+            })]
+        );
+    }
+
+    #[test]
+    fn mismatched_tags() {
+        let err = parse("<a>\n<b>\n</a></b>").unwrap_err();
+
+        assert_eq!(err.line, 3);
+        assert_eq!(err.message, "</a> closes <b>");
+    }
+
+    #[test]
+    fn unclosed_tags() {
+        assert_eq!(parse("<a><b></b>").unwrap_err().message, "<a> is never closed");
+        assert_eq!(parse("<a t=\"x>").unwrap_err().message, "unterminated attribute value");
+        assert_eq!(parse("<a><!-- x").unwrap_err().message, "missing -->");
+        assert_eq!(parse("<a/><b/>").unwrap_err().message, "content after the root element");
+    }
+}